@@ -9,9 +9,8 @@ pub fn alias(command: Vec<String>) -> Vec<String> {
             "--regex" | "--rev-regex" => {
                 command_vec.push(arg.to_string());
                 let regex_arg = command.next();
-                match regex_arg {
-                    Some(argument) => command_vec.push(argument.to_string()),
-                    _ => {}
+                if let Some(argument) = regex_arg {
+                    command_vec.push(argument.to_string())
                 };
             }
             _ => {
@@ -24,5 +23,5 @@ pub fn alias(command: Vec<String>) -> Vec<String> {
     }
     command_vec.insert(2, mass_command);
 
-    return command_vec;
+    command_vec
 }
@@ -1,191 +1,385 @@
 use crate::{
+    cli::forks::{resolve_fork_parents, set_upstream_remote},
     config::config::{Config, Profile},
-    git_api::git_providers::{get_provider, GitProvider, Repo},
-    utils::cmd::{create_git_cmd, run_cmd_o, run_cmd_o_soft, run_cmd_s},
-    utils::helper::{self, prntln, run_in_threads_default, MessageType},
+    git_api::client::git_http_config,
+    git_api::git_providers::{get_provider, GitProvider, ListOptions, Repo},
+    utils::cmd::{create_git_cmd, git_cmd, run_cmd_o_soft, run_cmd_r, GitConfig},
+    utils::helper::{default_threads, prntln, run_in_threads, MessageType},
+    utils::layout::Layout,
+    utils::progress::Progress,
 };
-use inquire::error;
 use regex::Regex;
 use reqwest::Client;
 use std::{
-    fs::remove_dir_all,
-    ops::ControlFlow,
+    collections::BTreeMap,
+    fs::{self, remove_dir_all},
+    io::{self, Read},
     path::Path,
-    process::{Command, Stdio},
     sync::Arc,
-    time::Instant,
 };
 
+pub struct CloneOptions {
+    pub force: bool,
+    pub list: ListOptions,
+    pub repo: bool,
+    pub from_file: Option<String>,
+    pub all_my_orgs: bool,
+    pub branch: String,
+    pub regex: String,
+    pub reverse: bool,
+    pub dry_run: bool,
+    pub jobs: Option<usize>,
+    pub quiet: bool,
+    pub with_upstream: bool,
+}
+
 pub async fn clone(
-    directory: &str,
-    force: bool,
-    user: bool,
-    branch: String,
-    regex: &str,
-    reverse: bool,
-    dry_run: bool,
+    directory: Option<&str>,
+    options: CloneOptions,
     config: Config,
     client: Arc<Client>,
 ) {
-    // let now = Instant::now();
-    let active_profile: Profile = config.active_profile().clone();
+    let active_profile: Profile = config.active_profile().with_resolved_token();
     let pat: Option<String> = Some(active_profile.clone().token);
     let provider_type: &str = &active_profile.provider;
+    let layout: Layout = load_layout(&active_profile);
     // Find amount of repositories
     let provider: Box<dyn GitProvider> = get_provider(provider_type);
-    let all_repos: Vec<Box<dyn Repo>> =
-        provider.get_repos(client, &pat, directory, user, active_profile.clone());
-    let re: Regex = Regex::new(regex).expect("Invalid regex pattern");
-    let repos_to_clone: Vec<Box<dyn Repo>> = all_repos
-        .into_iter()
-        .filter(|repo: &Box<dyn Repo>| {
-            (re.is_match(&repo.http_url()) || re.is_match(&repo.ssh_url())) ^ reverse
-        })
-        .collect();
-    // let elapsed = now.elapsed();
-    // println!("Elapsed: {:.2?}", elapsed);
-    if force {
+    let mut missing: usize = 0;
+    let all_repos: Vec<Box<dyn Repo>> = match (&options.from_file, directory) {
+        _ if options.all_my_orgs => get_all_my_orgs_repos(
+            provider.as_ref(),
+            Arc::clone(&client),
+            &pat,
+            &options.list,
+            &active_profile,
+        ),
+        _ if options.list.without_collection() => provider.get_repos(
+            Arc::clone(&client),
+            &pat,
+            "",
+            &options.list,
+            active_profile.clone(),
+        ),
+        (None, Some(directory)) if !options.repo => provider.get_repos(
+            Arc::clone(&client),
+            &pat,
+            directory,
+            &options.list,
+            active_profile.clone(),
+        ),
+        (from_file, directory) => {
+            let mut full_paths: Vec<String> = directory.map(str::to_string).into_iter().collect();
+            if let Some(from_file) = from_file {
+                full_paths.extend(read_repo_list(from_file));
+            }
+            provider
+                .get_repos_by_path(
+                    Arc::clone(&client),
+                    &pat,
+                    &full_paths,
+                    active_profile.clone(),
+                )
+                .into_iter()
+                .filter_map(|repo: Result<Box<dyn Repo>, String>| match repo {
+                    Ok(repo) => Some(repo),
+                    Err(err) => {
+                        prntln(&err, MessageType::Error);
+                        missing += 1;
+                        None
+                    }
+                })
+                .collect()
+        }
+    };
+    let mut repos_to_clone: Vec<Box<dyn Repo>> =
+        filter_repos(all_repos, &options.regex, options.reverse);
+    if options.with_upstream {
+        repos_to_clone = resolve_fork_parents(
+            provider.as_ref(),
+            client,
+            &pat,
+            repos_to_clone,
+            &active_profile,
+        );
+    }
+    let collisions: BTreeMap<String, Vec<String>> = layout
+        .find_collisions(&repos_to_clone)
+        .unwrap_or_else(|err: String| {
+            prntln(&err, MessageType::Error);
+            std::process::exit(1);
+        });
+    if !collisions.is_empty() {
+        for (path, full_paths) in &collisions {
+            prntln(
+                &format!(
+                    "Layout {} maps {} to the same directory {}",
+                    layout.template(),
+                    full_paths.join(", "),
+                    path
+                ),
+                MessageType::Error,
+            );
+        }
+        prntln(
+            "Adjust the layout of the profile or narrow down the repos with --regex.",
+            MessageType::Error,
+        );
+        std::process::exit(1);
+    }
+    if options.force {
         remove_directory_recursively(&active_profile.targetbasepath);
     }
 
+    if !options.quiet {
+        prntln(
+            &format!(
+                "\nCloning {} repositories from {}",
+                repos_to_clone.len(),
+                active_profile.baseaddress
+            ),
+            MessageType::Neutral,
+        );
+    }
+    let jobs: usize = options
+        .jobs
+        .unwrap_or_else(default_threads)
+        .clamp(1, repos_to_clone.len().max(1));
+    let progress: Arc<Progress> = Progress::new(
+        "Cloning",
+        repos_to_clone.len(),
+        jobs,
+        options.quiet,
+        !options.dry_run,
+    );
+    let worker_progress: Arc<Progress> = Arc::clone(&progress);
+    let branch: String = options.branch;
+    let dry_run: bool = options.dry_run;
+    let with_upstream: bool = options.with_upstream;
+    let git_config: GitConfig = git_http_config(&active_profile);
+    run_in_threads(jobs, repos_to_clone, move |thread_id: usize, repo| {
+        let git: &GitConfig = &git_config;
+        worker_progress.start(thread_id, repo.full_path());
+        let destination_path: String = layout
+            .destination(&active_profile.targetbasepath, repo.as_ref())
+            .expect("Every repo was rendered by find_collisions")
+            .to_string_lossy()
+            .to_string();
+        let clone_url = get_clone_url(&active_profile.pulloption, repo.as_ref());
+        let result: Result<String, String> = if Path::new(&destination_path).exists() {
+            pull(git, &branch, &destination_path, clone_url, dry_run)
+        } else {
+            run_cmd_r(
+                git_cmd(git).args(create_clone_args(&branch, clone_url, &destination_path)),
+                dry_run,
+            )
+            .map(|_| format!("Repository {} successfully cloned!", clone_url))
+            .map_err(|err: String| format!("Repository {} could not be cloned: {}", clone_url, err))
+        };
+        let result: Result<String, String> = match (result, repo.parent()) {
+            (Ok(message), Some(parent)) if with_upstream => set_upstream_remote(
+                git,
+                &destination_path,
+                get_clone_url(&active_profile.pulloption, parent),
+                dry_run,
+            )
+            .map(|_| format!("{} Upstream is {}", message, parent.full_path()))
+            .map_err(|err: String| {
+                format!(
+                    "Repository {} could not add upstream remote: {}",
+                    clone_url, err
+                )
+            }),
+            (result, _) => result,
+        };
+        worker_progress.finish(thread_id, result);
+    });
+
+    let (done, failed) = progress.stop();
+    let failed: usize = failed + missing;
+    if failed > 0 {
+        prntln(
+            &format!(
+                "\n\nFinished to clone {} repositories in {:.1?}, {} failed",
+                done,
+                progress.elapsed(),
+                failed
+            ),
+            MessageType::Error,
+        );
+        std::process::exit(1);
+    }
     prntln(
         &format!(
-            "\nCloning {} repositories from {}",
-            repos_to_clone.len(),
-            active_profile.baseaddress
+            "\n\nFinished to clone {} repositories in {:.1?}",
+            done,
+            progress.elapsed()
         ),
-        MessageType::Neutral,
+        MessageType::Success,
     );
-    run_in_threads_default(
-        repos_to_clone,
-        move |_thread_id: usize, repo: &Box<dyn Repo>| {
-            let destination_path: String =
-                format!("{}/{}", active_profile.targetbasepath, repo.full_path());
-            let clone_url = get_clone_url(&active_profile.pulloption, repo);
-            if Path::new(&destination_path).exists() {
-                pull(&branch, destination_path, clone_url, dry_run)
-            } else {
-                let status: bool = run_cmd_s(
-                    Command::new("git")
-                        .args(&create_clone_args(
-                            &branch,
-                            &clone_url,
-                            &active_profile.targetbasepath,
-                            &repo.full_path(),
-                        ))
-                        .stdout(Stdio::null())
-                        .stderr(Stdio::null()),
-                    dry_run,
-                    true,
-                );
-                if status {
-                    helper::prntln(
-                        &format!(
-                            "\n{} {} {}",
-                            "Repository", clone_url, "successfully cloned!"
-                        ),
-                        MessageType::Success,
-                    );
-                }
-                ControlFlow::Continue(())
-            }
-        },
-    );
-
-    // let elapsed = now.elapsed();
-    // println!("Elapsed: {:.2?}", elapsed);
-    prntln("\n\nFinished to clone repositories", MessageType::Success);
 }
 
 fn pull(
-    branch: &String,
-    destination_path: String,
+    git: &GitConfig,
+    branch: &str,
+    destination_path: &str,
     clone_url: &str,
     dry_run: bool,
-) -> ControlFlow<()> {
-    let current_branch = match get_current_pull_branch(branch, &destination_path, dry_run) {
-        Ok(value) => value,
-        Err(value) => return value,
-    };
-    let branch_exists: String = run_cmd_o(
-        create_git_cmd(&destination_path)
+) -> Result<String, String> {
+    let current_branch: String = get_current_pull_branch(git, branch, destination_path, dry_run)
+        .map_err(|err: String| format!("Repository {} could not be pulled: {}", clone_url, err))?;
+    let branch_exists: String = run_cmd_r(
+        create_git_cmd(git, destination_path)
             .arg("ls-remote")
             .arg("--heads")
             .arg("origin")
             .arg(&current_branch),
         dry_run,
-    );
-    if branch_exists != "" {
-        run_cmd_s(
-            create_git_cmd(&destination_path)
-                .arg("checkout")
-                .arg(&current_branch),
-            dry_run,
-            true,
-        );
-        run_cmd_s(create_git_cmd(&destination_path).arg("pull"), dry_run, true);
-        helper::prntln(
-            &format!("{} {} {}", "Repository", clone_url, "successfully pulled!"),
-            MessageType::Success,
-        );
+    )
+    .map_err(|err: String| format!("Repository {} could not be pulled: {}", clone_url, err))?;
+    if branch_exists.is_empty() && !dry_run {
+        return Ok(format!(
+            "Repository {} has no branch {} on origin, skipped pulling",
+            clone_url, current_branch
+        ));
     }
-    ControlFlow::Continue(())
+    run_cmd_r(
+        create_git_cmd(git, destination_path)
+            .arg("checkout")
+            .arg(&current_branch),
+        dry_run,
+    )
+    .and_then(|_| run_cmd_r(create_git_cmd(git, destination_path).arg("pull"), dry_run))
+    .map(|_| format!("Repository {} successfully pulled!", clone_url))
+    .map_err(|err: String| format!("Repository {} could not be pulled: {}", clone_url, err))
 }
 
 fn get_current_pull_branch(
+    git: &GitConfig,
     branch: &str,
-    destination_path: &String,
+    destination_path: &str,
     dry_run: bool,
-) -> Result<String, ControlFlow<()>> {
-    let current_branch: String = if branch == "" {
+) -> Result<String, String> {
+    let current_branch: String = if branch.is_empty() {
         let (symbolic_ref, success) = run_cmd_o_soft(
-            create_git_cmd(destination_path)
+            create_git_cmd(git, destination_path)
                 .arg("symbolic-ref")
                 .arg("refs/remotes/origin/HEAD")
                 .arg("--short"),
             dry_run,
         );
         if !success {
-            prntln(
-                "There is no HEAD branch defined in origin",
-                MessageType::Error,
-            );
-            return Err(ControlFlow::Break(()));
+            return Err("There is no HEAD branch defined in origin".to_string());
         }
-        symbolic_ref.strip_prefix("origin/").unwrap().to_string()
+        symbolic_ref
+            .strip_prefix("origin/")
+            .unwrap_or(&symbolic_ref)
+            .to_string()
     } else {
         branch.to_string()
     };
     Ok(current_branch)
 }
 
-fn get_clone_url<'a>(pulloption: &'a str, repo: &'a Box<dyn Repo>) -> &'a str {
+// Reads one repository path per line, empty lines and lines starting with # are skipped
+fn read_repo_list(from_file: &str) -> Vec<String> {
+    let content: io::Result<String> = if from_file == "-" {
+        let mut content: String = String::new();
+        io::stdin().read_to_string(&mut content).map(|_| content)
+    } else {
+        fs::read_to_string(from_file)
+    };
+    match content {
+        Ok(content) => content
+            .lines()
+            .map(str::trim)
+            .filter(|line: &&str| !line.is_empty() && !line.starts_with('#'))
+            .map(|line: &str| line.trim_matches('/').to_string())
+            .collect(),
+        Err(err) => {
+            prntln(
+                &format!("Could not read repository list {}: {}", from_file, err),
+                MessageType::Error,
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+// Lists the repos of every org the token owner is a member of
+pub(crate) fn get_all_my_orgs_repos(
+    provider: &dyn GitProvider,
+    client: Arc<Client>,
+    pat: &Option<String>,
+    options: &ListOptions,
+    active_profile: &Profile,
+) -> Vec<Box<dyn Repo>> {
+    let orgs: Vec<String> = provider
+        .get_own_collections(Arc::clone(&client), pat, active_profile.clone())
+        .unwrap_or_else(|err: String| {
+            prntln(&err, MessageType::Error);
+            std::process::exit(1);
+        });
+    orgs.iter()
+        .flat_map(|org: &String| {
+            provider.get_repos(
+                Arc::clone(&client),
+                pat,
+                org,
+                options,
+                active_profile.clone(),
+            )
+        })
+        .collect()
+}
+
+pub(crate) fn load_layout(profile: &Profile) -> Layout {
+    match Layout::new(&profile.layout) {
+        Ok(layout) => layout,
+        Err(err) => {
+            prntln(&err, MessageType::Error);
+            std::process::exit(1);
+        }
+    }
+}
+
+pub(crate) fn filter_repos(
+    repos: Vec<Box<dyn Repo>>,
+    regex: &str,
+    reverse: bool,
+) -> Vec<Box<dyn Repo>> {
+    let re: Regex = Regex::new(regex).expect("Invalid regex pattern");
+    repos
+        .into_iter()
+        .filter(|repo| (re.is_match(repo.http_url()) || re.is_match(repo.ssh_url())) ^ reverse)
+        .collect()
+}
+
+pub(crate) fn get_clone_url<'a>(pulloption: &'a str, repo: &'a dyn Repo) -> &'a str {
     let clone_url = if pulloption == "ssh" {
         repo.ssh_url()
     } else {
         repo.http_url()
     };
-    return clone_url;
+    clone_url
 }
 
-fn create_clone_args(
+pub(crate) fn create_clone_args(
     branch: &str,
     clone_url: &str,
-    target_basepath: &str,
-    directory: &str,
+    destination_path: &str,
 ) -> Vec<String> {
-    let mut args: Vec<String> = vec![
-        "clone".to_string(),
-        "--filter=blob:none".to_string(),
-        clone_url.to_string(),
-        format!("{}/{}", target_basepath, directory),
-    ];
-
-    if branch != "" {
-        args.insert(1, "-b".to_string());
-        args.insert(2, branch.to_string());
+    let mut args: Vec<String> = vec!["clone".to_string(), "--filter=blob:none".to_string()];
+    if !branch.is_empty() {
+        args.push(format!("--branch={}", branch));
     }
-    return args;
+    // The url and path can come from a lockfile or the API and must not be read as options
+    args.extend([
+        "--end-of-options".to_string(),
+        clone_url.to_string(),
+        destination_path.to_string(),
+    ]);
+    args
 }
 
 fn remove_directory_recursively(path: &str) {
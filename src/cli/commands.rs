@@ -1,15 +1,17 @@
 use clap::Subcommand;
 
+use crate::{config::config::Profile, git_api::git_providers::ListOptions};
+
 #[derive(Subcommand)]
 pub enum Commands {
     #[command(about = "Clone a repository into the specified directory.")]
     Clone {
         #[arg(
             value_name = "DIRECTORY",
-            required = true,
+            required_unless_present_any = ["from_file", "all_my_orgs", "membership", "starred"],
             help = "The group / org / user / repo to clone."
         )]
-        directory: String,
+        directory: Option<String>,
 
         #[arg(
             short,
@@ -19,13 +21,33 @@ pub enum Commands {
         )]
         force: bool,
 
+        #[clap(flatten)]
+        list_args: ListArgs,
+
         #[arg(
             short,
             long,
             default_value_t = false,
-            help = "Specify if the directory is a user directory or not (default false)."
+            conflicts_with = "user",
+            help = "Specify if the directory is a single repository like group/sub/repo (default false)."
         )]
-        user: bool,
+        repo: bool,
+
+        #[arg(
+            long,
+            value_name = "FILE",
+            conflicts_with_all = ["directory", "user", "repo", "membership", "starred"],
+            help = "Clone the repositories listed in a file, one group/sub/repo per line (- reads stdin)."
+        )]
+        from_file: Option<String>,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            conflicts_with_all = ["directory", "user", "repo", "from_file", "membership", "starred"],
+            help = "Use all orgs / top-level groups the token owner is a member of instead of a directory."
+        )]
+        all_my_orgs: bool,
 
         #[arg(
             short,
@@ -44,19 +66,252 @@ pub enum Commands {
             help = "Only make a dry run and list the commands which would be executed."
         )]
         dry_run: bool,
+
+        #[arg(
+            short,
+            long,
+            value_name = "N",
+            help = "Amount of repositories cloned in parallel (default amount of CPUs)."
+        )]
+        jobs: Option<usize>,
+
+        #[arg(
+            short,
+            long,
+            default_value_t = false,
+            help = "Only print errors and the final summary, useful for CI."
+        )]
+        quiet: bool,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Add an upstream remote pointing to the parent repository of forks."
+        )]
+        with_upstream: bool,
+    },
+    #[command(
+        visible_alias = "ls-remote",
+        about = "List the repositories of a group / org / user and whether they are cloned."
+    )]
+    List {
+        #[arg(
+            value_name = "DIRECTORY",
+            required_unless_present_any = ["all_my_orgs", "membership", "starred"],
+            help = "The group / org / user to list."
+        )]
+        directory: Option<String>,
+
+        #[clap(flatten)]
+        list_args: ListArgs,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            conflicts_with_all = ["directory", "user", "membership", "starred"],
+            help = "Use all orgs / top-level groups the token owner is a member of instead of a directory."
+        )]
+        all_my_orgs: bool,
+
+        #[clap(flatten)]
+        regex_args: Regex,
+
+        #[arg(
+            long,
+            value_parser = ["table", "json", "url"],
+            help = "Output format, url prints one clone url per line (default table or the format setting)."
+        )]
+        format: Option<String>,
+    },
+    #[command(
+        about = "Fast-forward the default branch of forks from their upstream and push it (github only)."
+    )]
+    SyncForks {
+        #[arg(
+            value_name = "DIRECTORY",
+            required = true,
+            help = "The org / user whose forks should be synced."
+        )]
+        directory: String,
+
+        #[clap(flatten)]
+        list_args: ListArgs,
+
+        #[clap(flatten)]
+        regex_args: Regex,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Sync through the merge-upstream API of the provider instead of the local checkouts."
+        )]
+        api: bool,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Only make a dry run and list the commands which would be executed."
+        )]
+        dry_run: bool,
+    },
+    #[command(
+        about = "Write the origin, branch and commit of all repos below the current directory into a lockfile."
+    )]
+    Lock {
+        #[arg(
+            short,
+            long,
+            default_value = "grgry.lock",
+            help = "The lockfile to write, a .json ending writes json instead of toml."
+        )]
+        output: String,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Fail instead of warning about uncommitted changes or commits which were not pushed."
+        )]
+        strict: bool,
+
+        #[clap(flatten)]
+        regex_args: Regex,
+    },
+    #[command(about = "Clone, fetch and checkout repos as recorded in a lockfile.")]
+    Restore {
+        #[arg(
+            value_name = "LOCKFILE",
+            default_value = "grgry.lock",
+            help = "The lockfile written by grgry lock."
+        )]
+        lockfile: String,
+
+        #[arg(
+            short,
+            long,
+            help = "Directory to restore into (default directory of the lockfile)."
+        )]
+        target: Option<String>,
+
+        #[arg(
+            short,
+            long,
+            value_name = "N",
+            help = "Amount of repositories restored in parallel (default amount of CPUs)."
+        )]
+        jobs: Option<usize>,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Only make a dry run and list the commands which would be executed."
+        )]
+        dry_run: bool,
     },
     #[command(about = "Make git add, git commit, git push in one go.")]
     Quick {
         #[arg(
             value_name = "MESSAGE",
-            required = true,
-            help = "Commit message same as git commit -m <MESSAGE>."
+            required_unless_present = "amend",
+            help = "Commit message same as git commit -m <MESSAGE>, {repo}, {path}, {branch} and {changed} are replaced per repo."
         )]
-        message: String,
+        message: Option<String>,
 
         #[arg(short, long, default_value_t = false)]
         force: bool,
 
+        #[arg(
+            long,
+            default_value_t = false,
+            conflicts_with = "no_push",
+            help = "Push with --force-with-lease, e.g. after --amend of a pushed commit."
+        )]
+        force_with_lease: bool,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Amend the last commit, keeps its message if no MESSAGE is given."
+        )]
+        amend: bool,
+
+        #[arg(
+            long,
+            value_name = "BRANCH",
+            help = "Switch to this branch before committing, it is created if it doesn't exist."
+        )]
+        branch: Option<String>,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Don't pull --rebase before pushing."
+        )]
+        no_pull: bool,
+
+        #[arg(long, default_value_t = false, help = "Only commit locally.")]
+        no_push: bool,
+
+        #[arg(
+            long,
+            value_name = "REMOTE",
+            default_value = "origin",
+            conflicts_with = "no_push",
+            help = "The remote to pull from and push to."
+        )]
+        push_to: String,
+
+        #[arg(
+            long = "type",
+            requires = "message",
+            value_name = "TYPE",
+            help = "Conventional commit type, the subject becomes TYPE(SCOPE): MESSAGE."
+        )]
+        commit_type: Option<String>,
+
+        #[arg(
+            long,
+            requires = "commit_type",
+            help = "Conventional commit scope, e.g. {repo}."
+        )]
+        scope: Option<String>,
+
+        #[arg(
+            long,
+            requires = "message",
+            value_name = "FILE",
+            help = "Read the commit body from a file (- reads stdin)."
+        )]
+        body_file: Option<String>,
+
+        #[arg(
+            long,
+            requires = "message",
+            value_name = "TRAILER",
+            help = "Add a trailer like \"Refs: JIRA-123\", can be given multiple times."
+        )]
+        trailer: Vec<String>,
+
+        #[arg(
+            long,
+            value_name = "REGEX",
+            help = "The subject must match this regex (default the commitpattern setting)."
+        )]
+        message_pattern: Option<String>,
+
+        #[arg(
+            long,
+            value_name = "PATHSPEC",
+            help = "Only stage changes matching this git pathspec, e.g. \"*.rs\", can be given multiple times."
+        )]
+        pathspec: Vec<String>,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Only stage changes of tracked files like git add -u."
+        )]
+        tracked_only: bool,
+
         #[clap(flatten)]
         regex_args: Regex,
 
@@ -106,8 +361,16 @@ pub enum Commands {
         about = "Manage your grgry profiles for different providers like github and gitlab."
     )]
     Profile {
+        // Boxed, the profile flags make it by far the largest command
         #[clap(subcommand)]
-        sub: ProfileCommands,
+        sub: Box<ProfileCommands>,
+    },
+    #[command(
+        about = "Inspect the configuration merged from the system, user and project config files."
+    )]
+    Config {
+        #[clap(subcommand)]
+        sub: ConfigCommands,
     },
     #[command(about = "EXPERIMENTAL: add this as git alias to simply use git for mass commands.")]
     Alias {
@@ -130,11 +393,99 @@ pub enum Commands {
 #[derive(Subcommand)]
 pub enum ProfileCommands {
     #[command(about = "Activate a profile to use.")]
-    Activate,
-    #[command(about = "Adding a profile interactively.")]
-    Add,
+    Activate {
+        #[arg(
+            value_name = "NAME",
+            help = "The profile to activate (default choose interactively)."
+        )]
+        name: Option<String>,
+    },
+    #[command(about = "Adding a profile, interactively if no fields are given.")]
+    Add {
+        #[arg(long, help = "The name of the profile (e.g. Github Profile).")]
+        name: Option<String>,
+
+        #[clap(flatten)]
+        fields: ProfileArgs,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Activate the profile after adding it."
+        )]
+        activate: bool,
+    },
+    #[command(
+        about = "Change single fields of a profile, without fields all of them are prompted."
+    )]
+    Edit {
+        #[arg(value_name = "NAME", required = true, help = "The profile to edit.")]
+        name: String,
+
+        #[clap(flatten)]
+        fields: ProfileArgs,
+    },
+    #[command(about = "Give a profile a new name.")]
+    Rename {
+        #[arg(value_name = "NAME", required = true, help = "The profile to rename.")]
+        name: String,
+
+        #[arg(value_name = "NEW_NAME", required = true, help = "The new name.")]
+        new_name: String,
+    },
+    #[command(
+        about = "Check required fields, provider, pull option, target base path and that the token authenticates."
+    )]
+    Validate {
+        #[arg(
+            value_name = "NAME",
+            help = "The profile to validate (default all profiles)."
+        )]
+        name: Option<String>,
+    },
     #[command(about = "Remove a unused or wrong profile.")]
-    Delete,
+    Delete {
+        #[arg(
+            value_name = "NAME",
+            help = "The profile to delete (default choose interactively)."
+        )]
+        name: Option<String>,
+    },
+    #[command(about = "Print a profile as config file to share it, e.g. with new teammates.")]
+    Export {
+        #[arg(value_name = "NAME", required = true, help = "The profile to export.")]
+        name: String,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Leave out the token unless it is a reference like env:NAME."
+        )]
+        redact_secrets: bool,
+    },
+    #[command(about = "Add the profiles of an exported file to the config.")]
+    Import {
+        #[arg(
+            value_name = "FILE",
+            required = true,
+            help = "The file written by grgry profile export, - reads stdin."
+        )]
+        file: String,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Overwrite profiles which already exist."
+        )]
+        force: bool,
+
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Import tokens from commands, the keyring, environment variables or the git credentials of other hosts without asking."
+        )]
+        allow_token_sources: bool,
+    },
     #[command(about = "Show the current activated profile.")]
     Show {
         #[arg(short, long, default_value_t = false, help = "Show all profiles.")]
@@ -142,6 +493,247 @@ pub enum ProfileCommands {
     },
 }
 
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    #[command(about = "Show all values of the merged configuration.")]
+    Show {
+        #[arg(
+            long,
+            default_value_t = false,
+            help = "Show the file or environment variable each value came from."
+        )]
+        origin: bool,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ProfileArgs {
+    #[clap(long, value_parser = ["github", "gitlab"], help = "The provider of the profile.")]
+    provider: Option<String>,
+    #[clap(
+        long,
+        help = "The base address of the provider (e.g. https://api.github.com)."
+    )]
+    base_address: Option<String>,
+    #[clap(
+        long,
+        conflicts_with = "token_env",
+        help = "The token or a reference like env:NAME, cmd:command, keyring:name, git-credential."
+    )]
+    token: Option<String>,
+    #[clap(
+        long,
+        value_name = "NAME",
+        help = "Read the token from this environment variable, same as --token env:NAME."
+    )]
+    token_env: Option<String>,
+    #[clap(long, help = "The absolute path where the repos should be cloned to.")]
+    target_base_path: Option<String>,
+    #[clap(long, help = "The name shown in the commit.")]
+    username: Option<String>,
+    #[clap(long, help = "The email shown in the commit.")]
+    email: Option<String>,
+    #[clap(long, value_parser = ["ssh", "https"], help = "Clone via ssh or https (default ssh).")]
+    pull_option: Option<String>,
+    #[clap(
+        long,
+        help = "Where repos are placed below the target base path (default {full_path})."
+    )]
+    layout: Option<String>,
+    #[clap(
+        long,
+        value_name = "SECONDS",
+        help = "Seconds a repo listing is reused from the cache (default 3600)."
+    )]
+    cache_ttl: Option<u64>,
+    #[clap(long, help = "Proxy URL for the provider API and git.")]
+    proxy: Option<String>,
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "PEM file with extra CA certificates."
+    )]
+    ca_bundle: Option<String>,
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "PEM file of the client certificate for mTLS."
+    )]
+    client_cert: Option<String>,
+    #[clap(
+        long,
+        value_name = "FILE",
+        help = "PEM file of the client key for mTLS."
+    )]
+    client_key: Option<String>,
+    #[clap(
+        long,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        help = "Use HTTP/1.1 instead of HTTP/2 (--http1 false switches it off again)."
+    )]
+    http1: Option<bool>,
+    #[clap(
+        long,
+        value_name = "KEY",
+        help = "GPG key id or SSH public key used to sign commits."
+    )]
+    signing_key: Option<String>,
+    #[clap(long, value_parser = ["openpgp", "ssh", "x509"], help = "The format of the signing key (default openpgp).")]
+    signing_format: Option<String>,
+    #[clap(
+        long,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        help = "Sign the commits of grgry quick (--gpg-sign false switches it off again)."
+    )]
+    gpg_sign: Option<bool>,
+    #[clap(
+        long,
+        num_args = 0..=1,
+        default_missing_value = "true",
+        help = "Write name, email and signing settings into the local git config of the repo, false uses -c for the single commit."
+    )]
+    write_git_config: Option<bool>,
+}
+impl ProfileArgs {
+    pub fn is_empty(&self) -> bool {
+        [
+            &self.provider,
+            &self.base_address,
+            &self.token,
+            &self.token_env,
+            &self.target_base_path,
+            &self.username,
+            &self.email,
+            &self.pull_option,
+            &self.layout,
+            &self.proxy,
+            &self.ca_bundle,
+            &self.client_cert,
+            &self.client_key,
+            &self.signing_key,
+            &self.signing_format,
+        ]
+        .iter()
+        .all(|flag: &&Option<String>| flag.is_none())
+            && self.cache_ttl.is_none()
+            && self.http1.is_none()
+            && self.gpg_sign.is_none()
+            && self.write_git_config.is_none()
+    }
+
+    // Overwrites the fields of the profile that were given as flags
+    pub fn apply(&self, profile: &mut Profile) {
+        let strings: [(&Option<String>, &mut String); 14] = [
+            (&self.provider, &mut profile.provider),
+            (&self.base_address, &mut profile.baseaddress),
+            (&self.token, &mut profile.token),
+            (&self.target_base_path, &mut profile.targetbasepath),
+            (&self.username, &mut profile.username),
+            (&self.email, &mut profile.email),
+            (&self.pull_option, &mut profile.pulloption),
+            (&self.layout, &mut profile.layout),
+            (&self.proxy, &mut profile.proxy),
+            (&self.ca_bundle, &mut profile.cabundle),
+            (&self.client_cert, &mut profile.clientcert),
+            (&self.client_key, &mut profile.clientkey),
+            (&self.signing_key, &mut profile.signingkey),
+            (&self.signing_format, &mut profile.signingformat),
+        ];
+        for (flag, field) in strings {
+            if let Some(value) = flag {
+                *field = value.clone();
+            }
+        }
+        if let Some(variable) = &self.token_env {
+            profile.token = format!("env:{}", variable);
+        }
+        if let Some(ttl) = self.cache_ttl {
+            profile.cachettl = ttl;
+        }
+        if let Some(http1) = self.http1 {
+            profile.http1 = http1;
+        }
+        if let Some(gpg_sign) = self.gpg_sign {
+            profile.gpgsign = gpg_sign;
+        }
+        if let Some(write_git_config) = self.write_git_config {
+            profile.writegitconfig = write_git_config;
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ListArgs {
+    #[clap(
+        short,
+        long,
+        default_value_t = false,
+        help = "Specify if the directory is a user directory, github detects it automatically (default false)."
+    )]
+    user: bool,
+    #[clap(
+        long,
+        value_delimiter = ',',
+        value_parser = ["owner", "collaborator", "organization_member"],
+        help = "Only list the repos of the token owner with these comma separated affiliations (github only)."
+    )]
+    affiliation: Vec<String>,
+    #[clap(
+        long,
+        value_parser = ["all", "public", "private"],
+        help = "Only list the repos of the token owner with this visibility (github only)."
+    )]
+    visibility: Option<String>,
+    #[clap(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["user", "directory"],
+        help = "Use all projects the token owner is a member of instead of a directory (gitlab only)."
+    )]
+    membership: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["user", "directory"],
+        help = "Use all projects the token owner starred instead of a directory (gitlab only)."
+    )]
+    starred: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Only the projects directly in the group without its subgroups (gitlab only)."
+    )]
+    no_subgroups: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Skip projects shared into the group from other namespaces (gitlab only)."
+    )]
+    no_shared: bool,
+    #[clap(
+        long,
+        default_value_t = false,
+        help = "Ignore the cached repo listing and fetch it again from the provider."
+    )]
+    refresh: bool,
+}
+impl ListArgs {
+    pub fn list_options(&self) -> ListOptions {
+        ListOptions {
+            user: self.user,
+            refresh: self.refresh,
+            affiliation: self.affiliation.clone(),
+            visibility: self.visibility.clone(),
+            membership: self.membership,
+            starred: self.starred,
+            no_subgroups: self.no_subgroups,
+            no_shared: self.no_shared,
+        }
+    }
+}
+
 #[derive(Debug, clap::Args)]
 #[group(multiple = false)]
 pub struct Regex {
@@ -161,11 +753,11 @@ impl Regex {
     pub fn get_regex_args(&self, default: &str) -> (String, bool) {
         // Determine the value and whether reverse is true
         if let Some(rev_regex) = &self.rev_regex {
-            return (rev_regex.clone(), true);
+            (rev_regex.clone(), true)
         } else if let Some(regex) = &self.regex {
-            return (regex.clone(), false);
+            (regex.clone(), false)
         } else {
-            return (default.to_string(), false);
+            (default.to_string(), false)
         }
     }
 }
@@ -0,0 +1,292 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use reqwest::Client;
+
+use crate::{
+    cli::clone::{filter_repos, get_clone_url, load_layout},
+    config::config::{Config, Profile},
+    git_api::client::git_http_config,
+    git_api::git_providers::{get_provider, GitProvider, ListOptions, Repo},
+    utils::{
+        cmd::{create_git_cmd, run_cmd_o_soft, run_cmd_r, GitConfig},
+        helper::{default_threads, prntln, run_in_threads, MessageType},
+        layout::Layout,
+        progress::Progress,
+    },
+};
+
+pub struct SyncForksOptions {
+    pub list: ListOptions,
+    pub regex: String,
+    pub reverse: bool,
+    pub api: bool,
+    pub dry_run: bool,
+}
+
+pub async fn sync_forks(
+    directory: &str,
+    options: SyncForksOptions,
+    config: Config,
+    client: Arc<Client>,
+) {
+    let active_profile: Profile = config.active_profile().with_resolved_token();
+    let pat: Option<String> = Some(active_profile.clone().token);
+    let layout: Layout = load_layout(&active_profile);
+    let provider: Box<dyn GitProvider> = get_provider(&active_profile.provider);
+    let all_repos: Vec<Box<dyn Repo>> = provider.get_repos(
+        Arc::clone(&client),
+        &pat,
+        directory,
+        &options.list,
+        active_profile.clone(),
+    );
+    let forks: Vec<Box<dyn Repo>> = filter_repos(all_repos, &options.regex, options.reverse)
+        .into_iter()
+        .filter(|repo| repo.fork())
+        .collect();
+    let forks: Vec<Box<dyn Repo>> = resolve_fork_parents(
+        provider.as_ref(),
+        Arc::clone(&client),
+        &pat,
+        forks,
+        &active_profile,
+    );
+
+    prntln(
+        &format!("\nSyncing {} forks from their upstream", forks.len()),
+        MessageType::Neutral,
+    );
+    let jobs: usize = match options.api {
+        true => 1,
+        false => default_threads().clamp(1, forks.len().max(1)),
+    };
+    let progress: Arc<Progress> =
+        Progress::new("Syncing", forks.len(), jobs, false, !options.dry_run);
+    if options.api {
+        // The API calls are cheap, so they are done one after another on the runtime
+        for fork in &forks {
+            progress.start(0, fork.full_path());
+            let result: Result<String, String> = if options.dry_run {
+                Ok(format!("Would sync fork {} via the API", fork.full_path()))
+            } else {
+                provider.sync_fork(
+                    Arc::clone(&client),
+                    &pat,
+                    fork.as_ref(),
+                    active_profile.clone(),
+                )
+            };
+            progress.finish(0, result);
+        }
+    } else {
+        let worker_progress: Arc<Progress> = Arc::clone(&progress);
+        let dry_run: bool = options.dry_run;
+        let git_config: GitConfig = git_http_config(&active_profile);
+        run_in_threads(jobs, forks, move |thread_id: usize, fork| {
+            worker_progress.start(thread_id, fork.full_path());
+            let result: Result<String, String> = layout
+                .destination(&active_profile.targetbasepath, fork.as_ref())
+                .and_then(|destination_path: PathBuf| {
+                    sync_fork_locally(
+                        &git_config,
+                        fork.as_ref(),
+                        &destination_path.to_string_lossy(),
+                        &active_profile.pulloption,
+                        dry_run,
+                    )
+                });
+            worker_progress.finish(thread_id, result);
+        });
+    }
+
+    let (done, failed) = progress.stop();
+    if failed > 0 {
+        prntln(
+            &format!("\n\nFinished to sync {} forks, {} failed", done, failed),
+            MessageType::Error,
+        );
+        std::process::exit(1);
+    }
+    prntln(
+        &format!("\n\nFinished to sync {} forks", done),
+        MessageType::Success,
+    );
+}
+
+// Replaces the forks in the list by their detailed version which contains the parent
+pub(crate) fn resolve_fork_parents(
+    provider: &dyn GitProvider,
+    client: Arc<Client>,
+    pat: &Option<String>,
+    repos: Vec<Box<dyn Repo>>,
+    active_profile: &Profile,
+) -> Vec<Box<dyn Repo>> {
+    let (forks, mut repos): (Vec<_>, Vec<_>) = repos
+        .into_iter()
+        .partition(|repo| repo.fork() && repo.parent().is_none());
+    if forks.is_empty() {
+        return repos;
+    }
+    let full_paths: Vec<String> = forks
+        .iter()
+        .map(|fork| fork.full_path().to_string())
+        .collect();
+    let details: Vec<Result<Box<dyn Repo>, String>> =
+        provider.get_repos_by_path(client, pat, &full_paths, active_profile.clone());
+    for (fork, detail) in forks.into_iter().zip(details) {
+        match detail {
+            Ok(detail) => repos.push(detail),
+            Err(err) => {
+                prntln(&err, MessageType::Error);
+                repos.push(fork);
+            }
+        }
+    }
+    repos
+}
+
+pub(crate) fn set_upstream_remote(
+    git: &GitConfig,
+    destination_path: &str,
+    upstream_url: &str,
+    dry_run: bool,
+) -> Result<String, String> {
+    let (_, exists) = run_cmd_o_soft(
+        create_git_cmd(git, destination_path).args(["remote", "get-url", "upstream"]),
+        dry_run,
+    );
+    let action: &str = if exists && !dry_run { "set-url" } else { "add" };
+    run_cmd_r(
+        create_git_cmd(git, destination_path).args(["remote", action, "upstream", upstream_url]),
+        dry_run,
+    )
+}
+
+// Fast-forwards the local branch if it exists, the checked out branch is merged to update the working tree
+fn update_local_branch(
+    git: &GitConfig,
+    destination_path: &str,
+    branch: &str,
+    upstream_ref: &str,
+    dry_run: bool,
+) -> Result<(), String> {
+    let current_branch: String = run_cmd_r(
+        create_git_cmd(git, destination_path).args(["branch", "--show-current"]),
+        dry_run,
+    )?;
+    if current_branch == branch {
+        return run_cmd_r(
+            create_git_cmd(git, destination_path).args(["merge", "--ff-only", upstream_ref]),
+            dry_run,
+        )
+        .map(|_| ());
+    }
+    let (_, exists) = run_cmd_o_soft(
+        create_git_cmd(git, destination_path).args([
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("refs/heads/{}", branch),
+        ]),
+        dry_run,
+    );
+    if !exists {
+        return Ok(());
+    }
+    // Fetching from the repo itself only allows fast-forwards
+    run_cmd_r(
+        create_git_cmd(git, destination_path).args([
+            "fetch",
+            ".",
+            &format!("{}:refs/heads/{}", upstream_ref, branch),
+        ]),
+        dry_run,
+    )
+    .map(|_| ())
+}
+
+fn sync_fork_locally(
+    git: &GitConfig,
+    fork: &dyn Repo,
+    destination_path: &str,
+    pulloption: &str,
+    dry_run: bool,
+) -> Result<String, String> {
+    let parent: &dyn Repo = fork
+        .parent()
+        .ok_or(format!("Fork {} has no known parent", fork.full_path()))?;
+    if !dry_run && !Path::new(destination_path).exists() {
+        return Err(format!(
+            "Fork {} is not cloned yet, clone it with grgry clone --with-upstream",
+            fork.full_path()
+        ));
+    }
+    let branch: &str = fork
+        .default_branch()
+        .ok_or(format!("Fork {} has no default branch", fork.full_path()))?;
+    let upstream_branch: &str = parent.default_branch().unwrap_or(branch);
+    let to_error = |err: String| format!("Fork {} could not be synced: {}", fork.full_path(), err);
+
+    set_upstream_remote(
+        git,
+        destination_path,
+        get_clone_url(pulloption, parent),
+        dry_run,
+    )
+    .map_err(to_error)?;
+    // The fork is updated without a checkout, so the working tree and current branch stay as they are
+    let upstream_ref: String = format!("refs/remotes/upstream/{}", upstream_branch);
+    let origin_ref: String = format!("refs/remotes/origin/{}", branch);
+    run_cmd_r(
+        create_git_cmd(git, destination_path).args(["fetch", "upstream", upstream_branch]),
+        dry_run,
+    )
+    .and_then(|_| {
+        run_cmd_r(
+            create_git_cmd(git, destination_path).args(["fetch", "origin", branch]),
+            dry_run,
+        )
+    })
+    .map_err(to_error)?;
+    let (_, fast_forward) = run_cmd_o_soft(
+        create_git_cmd(git, destination_path).args([
+            "merge-base",
+            "--is-ancestor",
+            &origin_ref,
+            &upstream_ref,
+        ]),
+        dry_run,
+    );
+    if !fast_forward {
+        return Err(to_error(format!(
+            "{} has commits which are not in upstream/{}",
+            branch, upstream_branch
+        )));
+    }
+    run_cmd_r(
+        create_git_cmd(git, destination_path).args([
+            "push",
+            "origin",
+            &format!("{}:refs/heads/{}", upstream_ref, branch),
+        ]),
+        dry_run,
+    )
+    .map_err(to_error)?;
+    update_local_branch(git, destination_path, branch, &upstream_ref, dry_run).map_err(
+        |err: String| {
+            to_error(format!(
+                "the fork was pushed, but the local {} could not be fast-forwarded: {}",
+                branch, err
+            ))
+        },
+    )?;
+    Ok(format!(
+        "Fork {} synced on branch {} from {}",
+        fork.full_path(),
+        branch,
+        parent.full_path()
+    ))
+}
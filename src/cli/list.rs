@@ -0,0 +1,165 @@
+use std::{path::PathBuf, sync::Arc};
+
+use colored::Colorize;
+use reqwest::Client;
+
+use crate::{
+    cli::clone::{filter_repos, get_all_my_orgs_repos, get_clone_url, load_layout},
+    config::config::{Config, Profile},
+    git_api::git_providers::{get_provider, GitProvider, ListOptions, Repo},
+    utils::{
+        helper::{prntln, MessageType},
+        layout::Layout,
+    },
+};
+
+pub struct ListReposOptions {
+    pub list: ListOptions,
+    pub all_my_orgs: bool,
+    pub regex: String,
+    pub reverse: bool,
+    pub format: String,
+}
+
+struct ListedRepo<'a> {
+    repo: &'a dyn Repo,
+    destination: PathBuf,
+    cloned: bool,
+}
+
+pub async fn list_repos(
+    directory: Option<&str>,
+    options: ListReposOptions,
+    config: Config,
+    client: Arc<Client>,
+) {
+    let active_profile: Profile = config.active_profile().with_resolved_token();
+    let pat: Option<String> = Some(active_profile.clone().token);
+    let layout: Layout = load_layout(&active_profile);
+    let provider: Box<dyn GitProvider> = get_provider(&active_profile.provider);
+    let all_repos: Vec<Box<dyn Repo>> = if options.all_my_orgs {
+        get_all_my_orgs_repos(
+            provider.as_ref(),
+            client,
+            &pat,
+            &options.list,
+            &active_profile,
+        )
+    } else {
+        // Without a directory membership or starred is used
+        provider.get_repos(
+            client,
+            &pat,
+            directory.unwrap_or(""),
+            &options.list,
+            active_profile.clone(),
+        )
+    };
+    let mut repos: Vec<Box<dyn Repo>> = filter_repos(all_repos, &options.regex, options.reverse);
+    repos.sort_by(|a, b| a.full_path().cmp(b.full_path()));
+    let listed: Vec<ListedRepo> = repos
+        .iter()
+        .filter_map(|repo| {
+            let destination: PathBuf = layout
+                .destination(&active_profile.targetbasepath, repo.as_ref())
+                .map_err(|err: String| prntln(&err, MessageType::Error))
+                .ok()?;
+            Some(ListedRepo {
+                repo: repo.as_ref(),
+                cloned: destination.join(".git").exists(),
+                destination,
+            })
+        })
+        .collect();
+
+    match options.format.as_str() {
+        "json" => print_json(&listed, &active_profile.pulloption),
+        "url" => {
+            for listed_repo in &listed {
+                println!(
+                    "{}",
+                    get_clone_url(&active_profile.pulloption, listed_repo.repo)
+                );
+            }
+        }
+        _ => print_table(&listed, &active_profile.pulloption),
+    }
+}
+
+fn print_table(listed: &[ListedRepo], pulloption: &str) {
+    let path_width: usize = listed
+        .iter()
+        .map(|listed_repo: &ListedRepo| listed_repo.repo.full_path().len())
+        .max()
+        .unwrap_or(0)
+        .max("REPOSITORY".len());
+    let branch_width: usize = listed
+        .iter()
+        .map(|listed_repo: &ListedRepo| listed_repo.repo.default_branch().unwrap_or("-").len())
+        .max()
+        .unwrap_or(0)
+        .max("BRANCH".len());
+    println!(
+        "{:<8} {:<path_width$} {:<branch_width$} URL",
+        "STATUS", "REPOSITORY", "BRANCH"
+    );
+    for listed_repo in listed {
+        // Pad before coloring, the escape codes would break the alignment
+        let status: String = format!(
+            "{:<8}",
+            if listed_repo.cloned {
+                "cloned"
+            } else {
+                "missing"
+            }
+        );
+        println!(
+            "{} {:<path_width$} {:<branch_width$} {}",
+            if listed_repo.cloned {
+                status.green()
+            } else {
+                status.red()
+            },
+            listed_repo.repo.full_path(),
+            listed_repo.repo.default_branch().unwrap_or("-"),
+            get_clone_url(pulloption, listed_repo.repo)
+        );
+    }
+    let cloned: usize = listed
+        .iter()
+        .filter(|listed_repo: &&ListedRepo| listed_repo.cloned)
+        .count();
+    prntln(
+        &format!(
+            "\n{} repositories, {} cloned, {} missing",
+            listed.len(),
+            cloned,
+            listed.len() - cloned
+        ),
+        MessageType::Neutral,
+    );
+}
+
+fn print_json(listed: &[ListedRepo], pulloption: &str) {
+    let json: Vec<serde_json::Value> = listed
+        .iter()
+        .map(|listed_repo: &ListedRepo| {
+            serde_json::json!({
+                "full_path": listed_repo.repo.full_path(),
+                "url": get_clone_url(pulloption, listed_repo.repo),
+                "ssh_url": listed_repo.repo.ssh_url(),
+                "http_url": listed_repo.repo.http_url(),
+                "default_branch": listed_repo.repo.default_branch(),
+                "fork": listed_repo.repo.fork(),
+                "archived": listed_repo.repo.archived(),
+                "topics": listed_repo.repo.topics(),
+                "path": listed_repo.destination.to_string_lossy(),
+                "cloned": listed_repo.cloned,
+            })
+        })
+        .collect();
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&json).expect("Failed to serialize repositories")
+    );
+}
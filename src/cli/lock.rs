@@ -0,0 +1,545 @@
+use std::{
+    env::current_dir,
+    fs,
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+
+use colored::Colorize;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cli::{clone::create_clone_args, mass::find_git_repos_parallel},
+    config::config::Config,
+    git_api::client::git_http_config,
+    utils::{
+        cmd::{create_git_cmd, git_cmd, run_cmd_o_soft, run_cmd_r, GitConfig},
+        helper::{default_threads, prntln, run_in_threads, MessageType},
+        progress::Progress,
+    },
+};
+
+const LOCKFILE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub version: u32,
+    #[serde(rename = "repo", default)]
+    pub repos: Vec<LockedRepo>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LockedRepo {
+    // Relative to the directory of the lockfile
+    pub path: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    pub commit: String,
+}
+
+pub fn lock(output: &str, regex: &str, reverse: bool, strict: bool) {
+    let root: PathBuf = current_dir().expect("Failed to get current directory");
+    let repos: Vec<PathBuf> = find_git_repos_parallel(None, regex, reverse);
+    let mut warnings: Vec<String> = Vec::new();
+    let mut locked_repos: Vec<LockedRepo> = repos
+        .par_iter()
+        .filter_map(|repo: &PathBuf| match lock_repo(&root, repo) {
+            Ok(locked_repo) => Some(locked_repo),
+            Err(err) => {
+                prntln(
+                    &format!("Repository {} skipped: {}", repo.display(), err),
+                    MessageType::Error,
+                );
+                None
+            }
+        })
+        .collect::<Vec<(LockedRepo, Vec<String>)>>()
+        .into_iter()
+        .map(|(locked_repo, repo_warnings)| {
+            warnings.extend(
+                repo_warnings
+                    .into_iter()
+                    .map(|warning: String| format!("Repository {} {}", locked_repo.path, warning)),
+            );
+            locked_repo
+        })
+        .collect();
+    locked_repos.sort_by(|a: &LockedRepo, b: &LockedRepo| a.path.cmp(&b.path));
+    // grgry restore can't reproduce changes which are not committed and pushed
+    warnings.sort();
+    for warning in &warnings {
+        eprintln!("{}", warning.yellow());
+    }
+    if strict && !warnings.is_empty() {
+        prntln(
+            "The workspace can't be reproduced exactly, no lockfile was written",
+            MessageType::Error,
+        );
+        std::process::exit(1);
+    }
+
+    let lockfile: Lockfile = Lockfile {
+        version: LOCKFILE_VERSION,
+        repos: locked_repos,
+    };
+    let content: String = if is_json(output) {
+        serde_json::to_string_pretty(&lockfile).expect("Failed to serialize lockfile")
+    } else {
+        toml::to_string_pretty(&lockfile).expect("Failed to serialize lockfile")
+    };
+    if let Err(err) = fs::write(output, content) {
+        prntln(
+            &format!("Could not write lockfile {}: {}", output, err),
+            MessageType::Error,
+        );
+        std::process::exit(1);
+    }
+    prntln(
+        &format!(
+            "Locked {} repositories into {}",
+            lockfile.repos.len(),
+            output
+        ),
+        MessageType::Success,
+    );
+}
+
+pub fn restore(
+    lockfile_path: &str,
+    target: Option<&str>,
+    jobs: Option<usize>,
+    dry_run: bool,
+    config: &Config,
+) {
+    let lockfile: Lockfile = read_lockfile(lockfile_path);
+    if lockfile.version > LOCKFILE_VERSION {
+        prntln(
+            &format!(
+                "Lockfile version {} is not supported by this grgry version, please update grgry.",
+                lockfile.version
+            ),
+            MessageType::Error,
+        );
+        std::process::exit(1);
+    }
+    // A shared lockfile must not clone or fetch outside of the target directory
+    let invalid_paths: Vec<&str> = lockfile
+        .repos
+        .iter()
+        .map(|locked_repo: &LockedRepo| locked_repo.path.as_str())
+        .filter(|path: &&str| !is_inside(Path::new(path)))
+        .collect();
+    if !invalid_paths.is_empty() {
+        prntln(
+            &format!(
+                "Lockfile {} contains paths outside of the target directory: {}",
+                lockfile_path,
+                invalid_paths.join(", ")
+            ),
+            MessageType::Error,
+        );
+        std::process::exit(1);
+    }
+    let invalid_repos: Vec<String> = lockfile
+        .repos
+        .iter()
+        .filter_map(|locked_repo: &LockedRepo| {
+            validate_locked_repo(locked_repo)
+                .err()
+                .map(|err: String| format!("{}: {}", locked_repo.path, err))
+        })
+        .collect();
+    if !invalid_repos.is_empty() {
+        prntln(
+            &format!(
+                "Lockfile {} contains invalid repositories: {}",
+                lockfile_path,
+                invalid_repos.join(", ")
+            ),
+            MessageType::Error,
+        );
+        std::process::exit(1);
+    }
+    let root: PathBuf = match target {
+        Some(target) => PathBuf::from(target),
+        None => Path::new(lockfile_path)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+    };
+
+    prntln(
+        &format!(
+            "\nRestoring {} repositories into {}",
+            lockfile.repos.len(),
+            root.display()
+        ),
+        MessageType::Neutral,
+    );
+    let jobs: usize = jobs
+        .unwrap_or_else(default_threads)
+        .clamp(1, lockfile.repos.len().max(1));
+    let progress: Arc<Progress> =
+        Progress::new("Restoring", lockfile.repos.len(), jobs, false, !dry_run);
+    let worker_progress: Arc<Progress> = Arc::clone(&progress);
+    // The network settings of --profile or the profile the repo is restored into
+    let repos: Vec<(LockedRepo, GitConfig)> = lockfile
+        .repos
+        .into_iter()
+        .map(|locked_repo: LockedRepo| {
+            let git: GitConfig = config
+                .profile_for_repo(&root.join(&locked_repo.path))
+                .map(git_http_config)
+                .unwrap_or_default();
+            (locked_repo, git)
+        })
+        .collect();
+    run_in_threads(
+        jobs,
+        repos,
+        move |thread_id: usize, (locked_repo, git): &(LockedRepo, GitConfig)| {
+            worker_progress.start(thread_id, &locked_repo.path);
+            let destination_path: String =
+                root.join(&locked_repo.path).to_string_lossy().to_string();
+            let result: Result<String, String> =
+                restore_repo(git, locked_repo, &destination_path, dry_run).map_err(
+                    |err: String| {
+                        format!(
+                            "Repository {} could not be restored: {}",
+                            locked_repo.path, err
+                        )
+                    },
+                );
+            worker_progress.finish(thread_id, result);
+        },
+    );
+
+    let (done, failed) = progress.stop();
+    if failed > 0 {
+        prntln(
+            &format!(
+                "\n\nFinished to restore {} repositories, {} failed",
+                done, failed
+            ),
+            MessageType::Error,
+        );
+        std::process::exit(1);
+    }
+    prntln(
+        &format!("\n\nFinished to restore {} repositories", done),
+        MessageType::Success,
+    );
+}
+
+// The locked repo and warnings why restoring it would differ from the workspace
+fn lock_repo(root: &Path, repo: &Path) -> Result<(LockedRepo, Vec<String>), String> {
+    let git: &GitConfig = &GitConfig::local();
+    let repo_path: String = repo.to_string_lossy().to_string();
+    let url: String = run_cmd_r(
+        create_git_cmd(git, &repo_path).args(["config", "--get", "remote.origin.url"]),
+        false,
+    )
+    .map_err(|_| "no origin remote configured".to_string())?;
+    let commit: String = run_cmd_r(
+        create_git_cmd(git, &repo_path).args(["rev-parse", "HEAD"]),
+        false,
+    )?;
+    let branch: String = run_cmd_r(
+        create_git_cmd(git, &repo_path).args(["branch", "--show-current"]),
+        false,
+    )?;
+    let mut warnings: Vec<String> = Vec::new();
+    let changes: String = run_cmd_r(
+        create_git_cmd(git, &repo_path).args(["status", "--porcelain", "--untracked-files=no"]),
+        false,
+    )?;
+    if !changes.is_empty() {
+        warnings.push("has uncommitted changes".to_string());
+    }
+    // Commits which are on no remote-tracking branch can't be fetched by restore
+    let unpushed: String = run_cmd_r(
+        create_git_cmd(git, &repo_path).args(["rev-list", "-n", "1", "HEAD", "--not", "--remotes"]),
+        false,
+    )?;
+    if !unpushed.is_empty() {
+        warnings.push(format!(
+            "is at commit {} which was not pushed",
+            short_commit(&commit)
+        ));
+    }
+    let path: String = repo
+        .strip_prefix(root)
+        .unwrap_or(repo)
+        .to_string_lossy()
+        .replace('\\', "/");
+    let locked_repo: LockedRepo = LockedRepo {
+        path: if path.is_empty() {
+            ".".to_string()
+        } else {
+            path
+        },
+        url,
+        branch: if branch.is_empty() {
+            None
+        } else {
+            Some(branch)
+        },
+        commit,
+    };
+    Ok((locked_repo, warnings))
+}
+
+fn restore_repo(
+    git: &GitConfig,
+    locked_repo: &LockedRepo,
+    destination_path: &str,
+    dry_run: bool,
+) -> Result<String, String> {
+    if Path::new(destination_path).exists() {
+        let (status, _) = run_cmd_o_soft(
+            create_git_cmd(git, destination_path).args(["status", "--porcelain"]),
+            dry_run,
+        );
+        if !status.is_empty() {
+            return Err("there are uncommitted changes".to_string());
+        }
+        run_cmd_r(
+            create_git_cmd(git, destination_path).args(["fetch", "origin"]),
+            dry_run,
+        )?;
+    } else {
+        run_cmd_r(
+            git_cmd(git).args(create_clone_args(
+                locked_repo.branch.as_deref().unwrap_or(""),
+                &locked_repo.url,
+                destination_path,
+            )),
+            dry_run,
+        )?;
+    }
+
+    // The commit might only be reachable from a ref which is not fetched by default
+    let (_, commit_exists) = run_cmd_o_soft(
+        create_git_cmd(git, destination_path).args([
+            "cat-file",
+            "-e",
+            &format!("{}^{{commit}}", locked_repo.commit),
+        ]),
+        dry_run,
+    );
+    if !commit_exists {
+        run_cmd_r(
+            create_git_cmd(git, destination_path).args([
+                "fetch",
+                "--end-of-options",
+                "origin",
+                &locked_repo.commit,
+            ]),
+            dry_run,
+        )?;
+    }
+
+    match &locked_repo.branch {
+        Some(branch) => {
+            let (local_commit, branch_exists) = run_cmd_o_soft(
+                create_git_cmd(git, destination_path).args([
+                    "rev-parse",
+                    "--verify",
+                    &format!("refs/heads/{}", branch),
+                ]),
+                dry_run,
+            );
+            if branch_exists && !dry_run && local_commit != locked_repo.commit {
+                // Only move the local branch forward, never drop local commits
+                run_cmd_r(
+                    create_git_cmd(git, destination_path).args([
+                        "merge-base",
+                        "--is-ancestor",
+                        "--end-of-options",
+                        branch,
+                        &locked_repo.commit,
+                    ]),
+                    dry_run,
+                )
+                .map_err(|_| format!("local branch {} has diverged from the lockfile", branch))?;
+            }
+            // checkout of older git versions doesn't know --end-of-options, the trailing -- at
+            // least keeps the commit from being read as path
+            run_cmd_r(
+                create_git_cmd(git, destination_path).args([
+                    "checkout",
+                    "-B",
+                    branch,
+                    &locked_repo.commit,
+                    "--",
+                ]),
+                dry_run,
+            )?;
+        }
+        None => {
+            run_cmd_r(
+                create_git_cmd(git, destination_path).args([
+                    "checkout",
+                    "--detach",
+                    &locked_repo.commit,
+                    "--",
+                ]),
+                dry_run,
+            )?;
+        }
+    }
+    Ok(format!(
+        "Repository {} restored at {}",
+        locked_repo.path,
+        short_commit(&locked_repo.commit)
+    ))
+}
+
+fn read_lockfile(lockfile_path: &str) -> Lockfile {
+    let content: String = match fs::read_to_string(lockfile_path) {
+        Ok(content) => content,
+        Err(err) => {
+            prntln(
+                &format!("Could not read lockfile {}: {}", lockfile_path, err),
+                MessageType::Error,
+            );
+            std::process::exit(1);
+        }
+    };
+    let lockfile: Result<Lockfile, String> = if is_json(lockfile_path) {
+        serde_json::from_str(&content).map_err(|err| err.to_string())
+    } else {
+        toml::from_str(&content).map_err(|err| err.to_string())
+    };
+    match lockfile {
+        Ok(lockfile) => lockfile,
+        Err(err) => {
+            prntln(
+                &format!("Lockfile {} is invalid: {}", lockfile_path, err),
+                MessageType::Error,
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+// Every value of a shared lockfile ends up on the git command line, so nothing may look like
+// an option
+fn validate_locked_repo(locked_repo: &LockedRepo) -> Result<(), String> {
+    if !is_commit_id(&locked_repo.commit) {
+        return Err(format!(
+            "commit {:?} is no full commit id",
+            locked_repo.commit
+        ));
+    }
+    if locked_repo.url.is_empty() || locked_repo.url.starts_with('-') {
+        return Err(format!("url {:?} is invalid", locked_repo.url));
+    }
+    if let Some(branch) = &locked_repo.branch {
+        let valid: bool = !branch.starts_with('-')
+            && run_cmd_r(
+                git_cmd(&GitConfig::local()).args(["check-ref-format", "--branch", branch]),
+                false,
+            )
+            .is_ok();
+        if !valid {
+            return Err(format!("branch {:?} is invalid", branch));
+        }
+    }
+    Ok(())
+}
+
+// The SHA-1 or SHA-256 id of a commit
+fn is_commit_id(commit: &str) -> bool {
+    [40, 64].contains(&commit.len()) && commit.chars().all(|c: char| c.is_ascii_hexdigit())
+}
+
+// A relative path without .. components
+fn is_inside(path: &Path) -> bool {
+    path.components()
+        .all(|component: Component| matches!(component, Component::Normal(_) | Component::CurDir))
+}
+
+fn is_json(path: &str) -> bool {
+    path.to_lowercase().ends_with(".json")
+}
+
+fn short_commit(commit: &str) -> &str {
+    // A hand edited lockfile can contain anything, get never panics on short or non-ASCII input
+    commit.get(..10).unwrap_or(commit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMMIT: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn locked_repo(url: &str, branch: Option<&str>, commit: &str) -> LockedRepo {
+        LockedRepo {
+            path: "group/repo".to_string(),
+            url: url.to_string(),
+            branch: branch.map(str::to_string),
+            commit: commit.to_string(),
+        }
+    }
+
+    #[test]
+    fn accepts_valid_repo() {
+        let url: &str = "git@example.com:group/repo.git";
+        assert!(validate_locked_repo(&locked_repo(url, Some("feature/x"), COMMIT)).is_ok());
+        assert!(validate_locked_repo(&locked_repo(url, None, &"a".repeat(64))).is_ok());
+    }
+
+    #[test]
+    fn rejects_commits_which_are_no_full_ids() {
+        let url: &str = "git@example.com:group/repo.git";
+        for commit in [
+            "--upload-pack=touch /tmp/pwned",
+            "0123456",
+            "main",
+            &"g".repeat(40),
+            &"a".repeat(41),
+        ] {
+            assert!(validate_locked_repo(&locked_repo(url, None, commit)).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_urls_looking_like_options() {
+        for url in ["", "--upload-pack=touch /tmp/pwned", "-u"] {
+            assert!(validate_locked_repo(&locked_repo(url, None, COMMIT)).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_branches() {
+        let url: &str = "git@example.com:group/repo.git";
+        for branch in ["--orphan", "-b", "a..b", "feature x", "ends/"] {
+            assert!(
+                validate_locked_repo(&locked_repo(url, Some(branch), COMMIT)).is_err(),
+                "{} was accepted",
+                branch
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_paths_outside_of_the_target() {
+        assert!(is_inside(Path::new("group/repo")));
+        assert!(is_inside(Path::new(".")));
+        assert!(!is_inside(Path::new("../repo")));
+        assert!(!is_inside(Path::new("group/../../repo")));
+        assert!(!is_inside(Path::new("/tmp/repo")));
+    }
+
+    #[test]
+    fn shortens_any_commit() {
+        assert_eq!(short_commit(COMMIT), "0123456789");
+        assert_eq!(short_commit("abc"), "abc");
+        // Byte 10 is inside the last ä
+        assert_eq!(short_commit("aäääää"), "aäääää");
+    }
+}
@@ -1,5 +1,7 @@
 use crate::{
-    utils::cmd::{create_git_cmd, run_cmd_s},
+    config::config::Config,
+    git_api::client::git_http_config,
+    utils::cmd::{create_git_cmd, run_cmd_s, GitConfig},
     utils::helper::{prntln, MessageType},
 };
 use colored::Colorize;
@@ -9,10 +11,18 @@ use regex::Regex;
 use std::{
     env::current_dir,
     path::{Path, PathBuf},
+    sync::Mutex,
 };
 use walkdir::WalkDir;
 
-pub fn mass(command: &str, regex: &str, reverse: bool, skip_interactive: bool, dry_run: bool) {
+pub fn mass(
+    command: &str,
+    regex: &str,
+    reverse: bool,
+    skip_interactive: bool,
+    dry_run: bool,
+    config: &Config,
+) {
     process_repos(
         regex,
         reverse,
@@ -44,13 +54,17 @@ pub fn mass(command: &str, regex: &str, reverse: bool, skip_interactive: bool, d
         },
         |repo| {
             let repo_path: std::borrow::Cow<'_, str> = repo.to_string_lossy();
+            let git: &GitConfig = &config
+                .profile_for_repo(repo)
+                .map(git_http_config)
+                .unwrap_or_default();
             let args: Vec<&str> = ["-C", &repo_path]
                 .iter()
                 .copied()
                 .chain(command.split_whitespace())
                 .collect();
 
-            run_cmd_s(create_git_cmd(&repo_path).args(args), dry_run, false);
+            run_cmd_s(create_git_cmd(git, &repo_path).args(args), dry_run, false);
         },
     );
 }
@@ -60,7 +74,7 @@ where
     F: Fn(&PathBuf) -> Result<bool, InquireError>,
     G: Fn(&PathBuf),
 {
-    let repos: Vec<PathBuf> = find_git_repos_parallel(None, &regex, reverse);
+    let repos: Vec<PathBuf> = find_git_repos_parallel(None, regex, reverse);
     for repo in repos {
         match interactive_fn(&repo) {
             Ok(true) => execute_fn(&repo),
@@ -70,18 +84,37 @@ where
     }
 }
 
+// Directory names from the ignore setting which are never searched for repos
+static DISCOVERY_IGNORE: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+pub fn set_discovery_ignore(names: Vec<String>) {
+    *DISCOVERY_IGNORE.lock().unwrap() = names;
+}
+
 //the root option is not set yet but could be included so the mass commands are not from the current but from another root dir
-fn find_git_repos_parallel(root: Option<&Path>, pattern: &str, reverse: bool) -> Vec<PathBuf> {
+pub(crate) fn find_git_repos_parallel(
+    root: Option<&Path>,
+    pattern: &str,
+    reverse: bool,
+) -> Vec<PathBuf> {
     let root: PathBuf = match root {
         Some(path) => path.to_path_buf(),
         None => current_dir().expect("Failed to get current directory"),
     };
     let regex: Regex = Regex::new(pattern).expect("Invalid regex pattern");
+    let ignore: Vec<String> = DISCOVERY_IGNORE.lock().unwrap().clone();
 
     WalkDir::new(root)
         .into_iter()
         .filter_entry(|entry| {
             let path = entry.path();
+            if entry.depth() > 0
+                && ignore
+                    .iter()
+                    .any(|name: &String| entry.file_name() == name.as_str())
+            {
+                return false;
+            }
             // Continue descending only if the directory does not contain a .git folder
             !path
                 .parent()
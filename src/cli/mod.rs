@@ -1,13 +1,21 @@
 pub(crate) mod alias;
 pub(crate) mod clone;
 pub mod commands;
+pub(crate) mod forks;
+pub(crate) mod list;
+pub(crate) mod lock;
 pub(crate) mod mass;
 pub mod profile;
 pub(crate) mod quick;
+pub(crate) mod settings;
 pub(crate) mod update;
 
 pub use alias::alias;
-pub use clone::clone;
-pub use mass::mass;
-pub use quick::quick;
+pub use clone::{clone, CloneOptions};
+pub use forks::{sync_forks, SyncForksOptions};
+pub use list::{list_repos, ListReposOptions};
+pub use lock::{lock, restore};
+pub use mass::{mass, set_discovery_ignore};
+pub use quick::{message_template, quick, QuickOptions};
+pub use settings::show_config;
 pub use update::update;
@@ -1,10 +1,33 @@
-use std::{collections::HashMap, process};
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, stdin, IsTerminal, Read},
+    path::Path,
+    process,
+    sync::Arc,
+};
 
-use inquire::{required, Confirm, InquireError, Select, Text};
+use colored::Colorize;
+use inquire::{required, validator::Validation, Confirm, CustomType, InquireError, Select, Text};
+use reqwest::Client;
+use toml_edit::{DocumentMut, Item, Table};
 
 use crate::{
-    config::config::{Config, Profile},
-    utils::helper::{prntln, MessageType},
+    cli::commands::ProfileArgs,
+    config::{
+        config::{Config, Profile},
+        schema::{empty_document, migrate},
+        token::{is_reference, local_token_source, resolve_token},
+    },
+    git_api::{
+        cache::DEFAULT_CACHE_TTL,
+        client::try_build_client,
+        git_providers::{get_provider, GitProvider},
+    },
+    utils::{
+        helper::{prntln, MessageType},
+        layout::{Layout, DEFAULT_LAYOUT},
+    },
 };
 
 pub fn show_profile(all: bool, config: Config) {
@@ -21,6 +44,17 @@ pub fn show_profile(all: bool, config: Config) {
     }
 }
 
+pub fn delete_profile(name: Option<&str>, config: &mut Config) {
+    match name {
+        Some(name) => {
+            exit_if_missing(name, config);
+            config.delete_profile(name);
+            prntln(&format!("Deleted profile {}", name), MessageType::Success);
+        }
+        None => delete_profile_prompt(config),
+    }
+}
+
 pub fn delete_profile_prompt(config: &mut Config) {
     let profiles_cloned: HashMap<String, Profile> = do_clone(&config.profiles);
     let profile_keys: Vec<&str> = profiles_cloned
@@ -40,18 +74,410 @@ pub fn delete_profile_prompt(config: &mut Config) {
     }
 }
 
+// Adds a profile from flags, only the missing required fields are prompted for on a terminal
+pub fn add_profile(name: Option<&str>, fields: &ProfileArgs, activate: bool, config: &mut Config) {
+    if name.is_none() && fields.is_empty() && !activate && stdin().is_terminal() {
+        add_profile_prompt(config);
+        return;
+    }
+
+    let name: String = match name {
+        Some(name) => name.to_string(),
+        None => required_field("--name", || {
+            Text::new("profile name:")
+                .with_validator(required!("This field is required"))
+                .prompt()
+        }),
+    };
+    if config.profiles.contains_key(&name) {
+        prntln(
+            &format!("Profile {} already exists, use grgry profile edit", name),
+            MessageType::Error,
+        );
+        process::exit(1);
+    }
+    let mut profile: Profile = Profile::default();
+    fields.apply(&mut profile);
+    if profile.provider.is_empty() {
+        profile.provider = required_field("--provider", || {
+            Select::new("choose provider", vec!["github", "gitlab"])
+                .prompt()
+                .map(String::from)
+        });
+    }
+    if profile.baseaddress.is_empty() {
+        profile.baseaddress = required_field("--base-address", || {
+            Text::new("base address:")
+                .with_validator(required!("This field is required"))
+                .prompt()
+        });
+    }
+    if profile.targetbasepath.is_empty() {
+        profile.targetbasepath = required_field("--target-base-path", || {
+            Text::new("target base path:")
+                .with_validator(required!("This field is required"))
+                .prompt()
+        });
+    }
+    exit_if_invalid_layout(&profile.layout);
+
+    config.profiles.insert(name.clone(), profile);
+    if activate {
+        config.activate_profile(&name);
+    }
+    config.save_config();
+    prntln(&format!("Added profile {}", name), MessageType::Success);
+}
+
+// Overwrites the given fields of an existing profile, without fields all of them are prompted
+pub fn edit_profile(name: &str, fields: &ProfileArgs, config: &mut Config) {
+    exit_if_missing(name, config);
+    if fields.is_empty() && !stdin().is_terminal() {
+        prntln(
+            "Nothing to change, pass the fields to edit like --email",
+            MessageType::Error,
+        );
+        process::exit(1);
+    }
+    let profile: &mut Profile = config.profiles.get_mut(name).unwrap();
+    match fields.is_empty() {
+        true => edit_profile_prompt(profile).unwrap_or_else(|_| process::exit(1)),
+        false => fields.apply(profile),
+    }
+    exit_if_invalid_layout(&profile.layout);
+    config.save_config();
+    prntln(
+        &serde_json::to_string_pretty(&config.profiles[name]).unwrap(),
+        MessageType::Neutral,
+    );
+}
+
+// Prompts every field with the current value prefilled
+fn edit_profile_prompt(profile: &mut Profile) -> Result<(), InquireError> {
+    profile.targetbasepath = Text::new("target base path:")
+        .with_validator(required!("This field is required"))
+        .with_initial_value(&profile.targetbasepath)
+        .prompt()?;
+    profile.username = Text::new("user name:")
+        .with_initial_value(&profile.username)
+        .prompt()?;
+    profile.email = Text::new("user email:")
+        .with_initial_value(&profile.email)
+        .prompt()?;
+    let providers: Vec<&str> = vec!["github", "gitlab"];
+    let current: usize = providers
+        .iter()
+        .position(|provider: &&str| *provider == profile.provider)
+        .unwrap_or(0);
+    profile.provider = Select::new("choose provider", providers)
+        .with_starting_cursor(current)
+        .prompt()?
+        .to_string();
+    profile.layout = Text::new("layout:")
+        .with_validator(|input: &str| match Layout::new(input) {
+            Ok(_) => Ok(Validation::Valid),
+            Err(err) => Ok(Validation::Invalid(err.into())),
+        })
+        .with_initial_value(&profile.layout)
+        .prompt()?;
+    profile.cachettl = CustomType::<u64>::new("cache ttl:")
+        .with_error_message("The ttl must be a number of seconds")
+        .with_default(profile.cachettl)
+        .prompt()?;
+    let pull_options: Vec<&str> = vec!["ssh", "https"];
+    let current: usize = pull_options
+        .iter()
+        .position(|option: &&str| *option == profile.pulloption)
+        .unwrap_or(0);
+    profile.pulloption = Select::new("choose pull option", pull_options)
+        .with_starting_cursor(current)
+        .prompt()?
+        .to_string();
+    profile.baseaddress = Text::new("base address:")
+        .with_validator(required!("This field is required"))
+        .with_initial_value(&profile.baseaddress)
+        .prompt()?;
+    // A plain token is not shown again, leaving it empty keeps it
+    let token: String = Text::new("token:")
+        .with_help_message("The token or env:NAME, cmd:command, keyring:name, git-credential, empty keeps the current token")
+        .with_initial_value(match is_reference(&profile.token) {
+            true => &profile.token,
+            false => "",
+        })
+        .prompt()?;
+    if !token.is_empty() {
+        profile.token = token;
+    }
+    Ok(())
+}
+
+pub fn rename_profile(name: &str, new_name: &str, config: &mut Config) {
+    exit_if_missing(name, config);
+    if config.profiles.contains_key(new_name) {
+        prntln(
+            &format!("Profile {} already exists", new_name),
+            MessageType::Error,
+        );
+        process::exit(1);
+    }
+    config.rename_profile(name, new_name);
+    prntln(
+        &format!("Renamed profile {} to {}", name, new_name),
+        MessageType::Success,
+    );
+}
+
+// Checks one or all profiles and exits with an error if any of them has problems
+pub fn validate_profiles(name: Option<&str>, config: &Config) {
+    let mut names: Vec<&String> = match name {
+        Some(name) => {
+            exit_if_missing(name, config);
+            config
+                .profiles
+                .keys()
+                .filter(|key: &&String| *key == name)
+                .collect()
+        }
+        None => config.profiles.keys().collect(),
+    };
+    names.sort();
+    let mut valid: bool = true;
+    for name in names {
+        let problems: Vec<String> = validate_profile(&config.profiles[name]);
+        if problems.is_empty() {
+            prntln(&format!("{} is valid", name), MessageType::Success);
+            continue;
+        }
+        valid = false;
+        prntln(&format!("{} has problems:", name), MessageType::Error);
+        for problem in problems {
+            prntln(&format!("  - {}", problem), MessageType::Error);
+        }
+    }
+    if !valid {
+        process::exit(1);
+    }
+}
+
+fn validate_profile(profile: &Profile) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    for (field, value) in [
+        ("baseaddress", &profile.baseaddress),
+        ("targetbasepath", &profile.targetbasepath),
+    ] {
+        if value.is_empty() {
+            problems.push(format!("{} is missing", field));
+        }
+    }
+    if !["github", "gitlab"].contains(&profile.provider.as_str()) {
+        problems.push(format!(
+            "provider {:?} is not github or gitlab",
+            profile.provider
+        ));
+    }
+    if !["ssh", "https"].contains(&profile.pulloption.as_str()) {
+        problems.push(format!(
+            "pulloption {:?} is not ssh or https",
+            profile.pulloption
+        ));
+    }
+    if !profile.targetbasepath.is_empty() && !Path::new(&profile.targetbasepath).is_dir() {
+        problems.push(format!(
+            "targetbasepath {} does not exist",
+            profile.targetbasepath
+        ));
+    }
+    if let Err(err) = Layout::new(&profile.layout) {
+        problems.push(format!("layout: {}", err));
+    }
+    if !["", "openpgp", "ssh", "x509"].contains(&profile.signingformat.as_str()) {
+        problems.push(format!(
+            "signingformat {:?} is not openpgp, ssh or x509",
+            profile.signingformat
+        ));
+    }
+    // The network settings are checked without a token too, they are used for git as well
+    let client: Option<Client> = match try_build_client(profile) {
+        Ok(client) => Some(client),
+        Err(err) => {
+            problems.push(err);
+            None
+        }
+    };
+    // The token can only be checked if the fields needed for the request are fine
+    let Some(client) = client.filter(|_| problems.is_empty() && !profile.token.is_empty()) else {
+        return problems;
+    };
+    let token: String = match resolve_token(&profile.token, &profile.baseaddress) {
+        Ok(token) => token,
+        Err(err) => {
+            problems.push(err);
+            return problems;
+        }
+    };
+    let provider: Box<dyn GitProvider> = get_provider(&profile.provider);
+    if let Err(err) = provider.get_token_owner(Arc::new(client), &Some(token), profile.clone()) {
+        problems.push(format!("token does not authenticate: {}", err));
+    }
+    problems
+}
+
+// Prints the profile as a config file which grgry profile import understands
+pub fn export_profile(name: &str, redact_secrets: bool, config: &Config) {
+    exit_if_missing(name, config);
+    let mut profiles: Table = Table::new();
+    profiles.set_implicit(true);
+    profiles.insert(
+        name,
+        Item::Table(config.profiles[name].to_table(redact_secrets)),
+    );
+    let mut doc: DocumentMut = empty_document();
+    doc["profiles"] = Item::Table(profiles);
+    print!("{}", doc);
+}
+
+// Merges the profiles of an exported file into the user config, keeping its comments
+pub fn import_profiles(file: &str, force: bool, allow_token_sources: bool, config: &mut Config) {
+    let content: io::Result<String> = if file == "-" {
+        let mut content: String = String::new();
+        stdin().read_to_string(&mut content).map(|_| content)
+    } else {
+        fs::read_to_string(file)
+    };
+    let doc: DocumentMut = match content.map(|content: String| content.parse::<DocumentMut>()) {
+        Ok(Ok(doc)) => migrate(&doc, Path::new(file)),
+        Ok(Err(err)) => exit_with(&format!("{} is no valid toml: {}", file, err)),
+        Err(err) => exit_with(&format!("Could not read {}: {}", file, err)),
+    };
+    let profiles: Vec<(&str, &Table)> = doc
+        .get("profiles")
+        .and_then(Item::as_table)
+        .map(|profiles: &Table| {
+            profiles
+                .iter()
+                .filter_map(|(name, item)| item.as_table().map(|table: &Table| (name, table)))
+                .collect()
+        })
+        .unwrap_or_default();
+    if profiles.is_empty() {
+        exit_with(&format!("{} contains no profiles", file));
+    }
+    if !force {
+        if let Some((name, _)) = profiles
+            .iter()
+            .find(|(name, _)| config.profiles.contains_key(*name))
+        {
+            exit_with(&format!(
+                "Profile {} already exists, rename it first or use --force",
+                name
+            ));
+        }
+    }
+    let profiles: Vec<(&str, Profile)> = profiles
+        .into_iter()
+        .map(|(name, table)| (name, Profile::from_table(table)))
+        .collect();
+    // A shared file must not run commands or send secrets of this machine to a host it chooses
+    // without the user knowing
+    let sources: Vec<String> = profiles
+        .iter()
+        .filter_map(|(name, profile)| {
+            local_token_source(&profile.token, &profile.baseaddress).map(|source: String| {
+                format!("  {}: {}, sent to {}", name, source, profile.baseaddress)
+            })
+        })
+        .collect();
+    if !allow_token_sources && !sources.is_empty() {
+        eprintln!(
+            "{}",
+            format!(
+                "These profiles get their token from this machine:\n{}",
+                sources.join("\n")
+            )
+            .yellow()
+        );
+        // stdin might be the file itself
+        if file == "-" || !stdin().is_terminal() {
+            exit_with("Use --allow-token-sources to import these profiles");
+        }
+        let allowed: bool = Confirm::new("Do you want to import them with these tokens?")
+            .with_default(false)
+            .prompt()
+            .unwrap_or_else(|_| process::exit(1));
+        if !allowed {
+            process::exit(1);
+        }
+    }
+
+    for (name, profile) in profiles {
+        let without_token: bool = profile.token.is_empty();
+        config.profiles.insert(name.to_string(), profile);
+        prntln(&format!("Imported profile {}", name), MessageType::Success);
+        if without_token {
+            prntln(
+                &format!(
+                    "Profile {} has no token, add one with grgry profile edit \"{}\" --token <TOKEN>",
+                    name, name
+                ),
+                MessageType::Neutral,
+            );
+        }
+    }
+    config.save_config();
+}
+
+fn exit_with(message: &str) -> ! {
+    prntln(message, MessageType::Error);
+    process::exit(1);
+}
+
+// Without a terminal there is nobody to ask, so a missing flag is an error
+fn required_field<F>(flag: &str, prompt: F) -> String
+where
+    F: FnOnce() -> Result<String, InquireError>,
+{
+    if !stdin().is_terminal() {
+        prntln(
+            &format!("{} is required when not running in a terminal", flag),
+            MessageType::Error,
+        );
+        process::exit(1);
+    }
+    prompt().unwrap_or_else(|_| process::exit(1))
+}
+
+fn exit_if_missing(name: &str, config: &Config) {
+    if !config.profiles.contains_key(name) {
+        prntln(
+            &format!("Profile {} does not exist", name),
+            MessageType::Error,
+        );
+        process::exit(1);
+    }
+}
+
+fn exit_if_invalid_layout(layout: &str) {
+    if let Err(err) = Layout::new(layout) {
+        prntln(&err, MessageType::Error);
+        process::exit(1);
+    }
+}
+
 pub fn add_profile_prompt(config: &mut Config) {
+    let existing_names: Vec<String> = config.profiles.keys().cloned().collect();
     let profile_name: Result<String, InquireError> = Text::new("profile name:")
         .with_validator(required!("This field is required"))
-        //TODO: would be nice to validate if the profile already exists but does not work due to my rust incapabilities
-        // .with_validator(
-        //     move |name: &str| {
-        //     if choices.iter().any(|e| name.contains(e)) {
-        //         Ok(Validation::Invalid("not exists".into()))
-        //     } else {
-        //         Ok(Validation::Valid)
-        //     }
-        // })
+        .with_validator(move |name: &str| {
+            match existing_names
+                .iter()
+                .any(|existing: &String| existing == name)
+            {
+                true => Ok(Validation::Invalid(
+                    "A profile with this name already exists".into(),
+                )),
+                false => Ok(Validation::Valid),
+            }
+        })
         .with_help_message("The name of your profile (e.g. Github Profile)")
         .prompt();
     match profile_name {
@@ -96,6 +522,36 @@ pub fn add_profile_prompt(config: &mut Config) {
         Err(_) => process::exit(1),
     };
 
+    let layout: Result<String, InquireError> = Text::new("layout:")
+        .with_validator(|input: &str| match Layout::new(input) {
+            Ok(_) => Ok(Validation::Valid),
+            Err(err) => Ok(Validation::Invalid(err.into())),
+        })
+        .with_help_message(
+            "Where repos are placed below the target base path, using {host}, {full_path}, {namespace}, {namespace_last} and {name}",
+        )
+        .with_default(DEFAULT_LAYOUT)
+        .prompt();
+    match layout {
+        Ok(_) => {}
+        Err(_) => process::exit(1),
+    };
+
+    let cache_ttl: Result<String, InquireError> = Text::new("cache ttl:")
+        .with_validator(|input: &str| match input.parse::<u64>() {
+            Ok(_) => Ok(Validation::Valid),
+            Err(_) => Ok(Validation::Invalid("The ttl must be a number of seconds".into())),
+        })
+        .with_help_message(
+            "Seconds a repo listing is reused from the cache without asking the provider (0 always revalidates)",
+        )
+        .with_default(&DEFAULT_CACHE_TTL.to_string())
+        .prompt();
+    match cache_ttl {
+        Ok(_) => {}
+        Err(_) => process::exit(1),
+    };
+
     let pull_options: Vec<&str> = vec!["ssh", "https"];
     let pulloption: Result<&str, InquireError> =
         Select::new("choose pull option", pull_options).prompt();
@@ -115,7 +571,7 @@ pub fn add_profile_prompt(config: &mut Config) {
 
     let token: Result<String, InquireError> = Text::new("token:")
         .with_help_message(
-            "The token to access the provider, if empty only public repos can be cloned",
+            "The token or env:NAME, cmd:command, keyring:name, git-credential, if empty only public repos can be cloned",
         )
         .with_default("")
         .prompt();
@@ -144,13 +600,13 @@ pub fn add_profile_prompt(config: &mut Config) {
             baseaddress: base_address.unwrap(),
             provider: String::from(provider.unwrap()),
             token: token.unwrap(),
+            layout: layout.unwrap(),
+            cachettl: cache_ttl.unwrap().parse::<u64>().unwrap(),
+            ..Profile::default()
         },
     );
-    match activate {
-        Ok(true) => {
-            config.activate_profile(&profile_name.clone());
-        }
-        _ => {}
+    if let Ok(true) = activate {
+        config.activate_profile(&profile_name.clone());
     }
     config.save_config();
     println!("{}", serde_json::to_string_pretty(&config).unwrap());
@@ -160,6 +616,20 @@ fn do_clone<K: Clone, V: Clone>(data: &HashMap<K, V>) -> HashMap<K, V> {
     data.clone()
 }
 
+pub fn activate_profile(name: Option<&str>, config: &mut Config) {
+    match name {
+        Some(name) => {
+            exit_if_missing(name, config);
+            config.activate_profile(name);
+            prntln(
+                &format!("{} {}", "Activated profile is:", name),
+                MessageType::Success,
+            );
+        }
+        None => activate_profile_prompt(config),
+    }
+}
+
 pub fn activate_profile_prompt(config: &mut Config) {
     let profiles_cloned: HashMap<String, Profile> = do_clone(&config.profiles);
     let profile_keys: Vec<&str> = profiles_cloned
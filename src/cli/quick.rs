@@ -1,49 +1,86 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    env::current_dir,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
 use colored::Colorize;
-use inquire::{validator::Validation, CustomType, Select};
+use inquire::{validator::Validation, CustomType, MultiSelect, Select};
 
 use crate::{
     config::config::{Config, Profile},
-    utils::cmd::{create_git_cmd, run_cmd_o, run_cmd_s},
+    git_api::client::git_http_config,
+    utils::cmd::{create_git_cmd, run_cmd_o, run_cmd_o_soft, run_cmd_r, run_cmd_s, GitConfig},
     utils::helper::{prntln, MessageType},
+    utils::message::{MessageTemplate, MessageValues},
 };
 
-use super::mass::process_repos;
+use super::mass::{find_git_repos_parallel, process_repos};
 
-pub fn quick(
-    message: &str,
-    force: bool,
-    regex: &str,
-    reverse: bool,
-    skip_interactive: bool,
-    dry_run: bool,
-    config: Config,
-) {
+pub struct QuickOptions {
+    pub force: bool,
+    pub force_with_lease: bool,
+    pub regex: String,
+    pub reverse: bool,
+    pub skip_interactive: bool,
+    pub dry_run: bool,
+    // Only changes matching one of the pathspecs are staged, all if empty
+    pub pathspecs: Vec<String>,
+    pub tracked_only: bool,
+    pub no_pull: bool,
+    pub no_push: bool,
+    pub amend: bool,
+    // Created or switched to before committing
+    pub branch: Option<String>,
+    pub push_to: String,
+}
+
+// Without a template the message of an amended commit is kept
+pub fn quick(template: Option<&MessageTemplate>, options: QuickOptions, config: Config) {
+    let dry_run: bool = options.dry_run;
+    let message_values: HashMap<PathBuf, MessageValues> = match template {
+        Some(template) => check_messages(template, &options),
+        None => HashMap::new(),
+    };
+    // A failing repo is reported at the end instead of aborting the other repos
+    let failures: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    // The files picked in the interactive mode, staged instead of the pathspecs
+    let picked: RefCell<HashMap<PathBuf, Vec<String>>> = RefCell::new(HashMap::new());
+    // Looking at the changes only needs the local repo
+    let local: GitConfig = GitConfig::local();
     process_repos(
-        regex,
-        reverse,
+        &options.regex,
+        options.reverse,
         |repo| {
+            let git: &GitConfig = &local;
             let repo_path = repo.to_string_lossy();
             let has_changes = !run_cmd_o(
-                create_git_cmd(&repo_path).arg("status").arg("--porcelain"),
+                create_git_cmd(git, &repo_path).args(status_args(&options)),
                 dry_run,
             )
             .is_empty();
 
-            if !has_changes {
+            // Amending can also only change the message
+            if !has_changes && !options.amend {
                 return Ok(false);
             }
 
             prntln(
-                &format!("There are changes in the repository {}", repo.display()),
+                &match has_changes {
+                    true => format!("There are changes in the repository {}", repo.display()),
+                    false => format!("The last commit of {} is amended", repo.display()),
+                },
                 MessageType::Neutral,
             );
 
-            if skip_interactive {
+            if options.skip_interactive {
                 return Ok(true);
             }
 
             loop {
-
                 let result = CustomType::<String>::new(
                     "Do you want to quicken this repo? (y)es/(n)o/(m)ore information:",
                 )
@@ -61,20 +98,35 @@ pub fn quick(
                         "y" => return Ok(true),
                         "n" => return Ok(false),
                         "m" => {
-                            run_cmd_s(create_git_cmd(&repo_path).arg("diff"), dry_run, false);
+                            run_cmd_s(create_git_cmd(git, &repo_path).arg("diff"), dry_run, false);
                             prntln(
-                                &format!("{:<10}: {}", "URL", get_remote_url(&repo_path, dry_run)),
+                                &format!(
+                                    "{:<10}: {}",
+                                    "URL",
+                                    get_remote_url(git, &repo_path, &options.push_to, dry_run)
+                                        .unwrap_or_else(|err: String| err)
+                                ),
                                 MessageType::Success,
                             );
                             prntln(
                                 &format!(
                                     "{:<10}: {}",
                                     "Branch",
-                                    get_current_branch(&repo_path, dry_run)
+                                    get_current_branch(git, &repo_path, dry_run)
+                                        .unwrap_or_else(|err: String| err)
                                 ),
                                 MessageType::Success,
                             );
-                            // Ok(false)
+                            let files: Vec<String> = changed_files(git, &repo_path, &options);
+                            let all: Vec<usize> = (0..files.len()).collect();
+                            let selected: Vec<String> = MultiSelect::new("Files to commit:", files)
+                                .with_default(&all)
+                                .prompt()?;
+                            if selected.is_empty() && !options.amend {
+                                prntln("No files selected, skipping.", MessageType::Neutral);
+                                return Ok(false);
+                            }
+                            picked.borrow_mut().insert(repo.clone(), selected);
                         }
                         _ => unreachable!(),
                     },
@@ -84,29 +136,254 @@ pub fn quick(
         },
         |repo| {
             let repo_path = repo.to_string_lossy();
-            let remote_url = get_remote_url(&repo_path, dry_run);
-            let profile = select_profile(&config, &remote_url);
+            // Without origin the profile can still be found by the location of the repo
+            let remote_url: String =
+                get_remote_url(&local, &repo_path, "origin", dry_run).unwrap_or_default();
+            let profile = select_profile(&config, repo, &remote_url);
+            let git: &GitConfig = &git_http_config(profile);
 
-            execute_quick_actions(&repo_path, &profile, message, dry_run);
+            let message: Option<(&MessageTemplate, &MessageValues)> =
+                template.zip(message_values.get(repo));
+            // No picked files only amends, git add without pathspecs would stage everything
+            let add_args: Vec<String> = match picked.borrow_mut().remove(repo) {
+                Some(files) if files.is_empty() => Vec::new(),
+                Some(files) => ["add", "-A", "--"]
+                    .iter()
+                    .map(|arg: &&str| arg.to_string())
+                    .chain(files)
+                    .collect(),
+                None => add_args(&options),
+            };
+            if let Err(err) =
+                execute_quick_actions(git, &repo_path, profile, message, &add_args, &options)
+            {
+                prntln(&format!("{}: {}", repo.display(), err), MessageType::Error);
+                // The summary only repeats the first line, the details were printed above
+                let reason: &str = err.lines().next().unwrap_or_default();
+                failures
+                    .borrow_mut()
+                    .push(format!("{}: {}", repo.display(), reason));
+            }
         },
     );
+    let failures: Vec<String> = failures.into_inner();
+    if !failures.is_empty() {
+        prntln(
+            &format!("\n{} repositories failed:", failures.len()),
+            MessageType::Error,
+        );
+        for failure in failures {
+            prntln(&failure, MessageType::Error);
+        }
+        std::process::exit(1);
+    }
 }
 
-fn get_remote_url(repo_path: &str, dry_run: bool) -> String {
-    run_cmd_o(
-        create_git_cmd(repo_path).args(&["config", "--get", "remote.origin.url"]),
+// Builds the message template from the quick arguments, exits if it is invalid
+pub fn message_template(
+    message: &str,
+    commit_type: Option<&str>,
+    scope: Option<&str>,
+    body_file: Option<&str>,
+    trailers: &[String],
+    pattern: Option<&str>,
+) -> MessageTemplate {
+    let body: Option<String> = body_file.map(|body_file: &str| {
+        let content: io::Result<String> = if body_file == "-" {
+            let mut content: String = String::new();
+            io::stdin().read_to_string(&mut content).map(|_| content)
+        } else {
+            fs::read_to_string(body_file)
+        };
+        content.unwrap_or_else(|err| {
+            prntln(
+                &format!("Could not read body file {}: {}", body_file, err),
+                MessageType::Error,
+            );
+            std::process::exit(1);
+        })
+    });
+    MessageTemplate::new(
+        message,
+        commit_type,
+        scope,
+        body.as_deref(),
+        trailers,
+        pattern,
+    )
+    .unwrap_or_else(|err: String| {
+        prntln(&err, MessageType::Error);
+        std::process::exit(1);
+    })
+}
+
+// Renders the messages of all repos which will be committed first, so a bad message fails
+// before anything is committed. {changed} is counted again after staging, as the interactive
+// mode can pick other files.
+fn check_messages(
+    template: &MessageTemplate,
+    options: &QuickOptions,
+) -> HashMap<PathBuf, MessageValues> {
+    let git: &GitConfig = &GitConfig::local();
+    let dry_run: bool = options.dry_run;
+    let cwd: PathBuf = current_dir().expect("Failed to get current directory");
+    let mut message_values: HashMap<PathBuf, MessageValues> = HashMap::new();
+    let mut errors: Vec<String> = Vec::new();
+    for repo in find_git_repos_parallel(None, &options.regex, options.reverse) {
+        let repo_path = repo.to_string_lossy();
+        let changed: usize = run_cmd_o(
+            create_git_cmd(git, &repo_path).args(status_args(options)),
+            dry_run,
+        )
+        .lines()
+        .count();
+        if changed == 0 && !options.amend {
+            continue;
+        }
+        let values: MessageValues = MessageValues {
+            repo: repo
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            path: repo
+                .strip_prefix(&cwd)
+                .unwrap_or(&repo)
+                .to_string_lossy()
+                .to_string(),
+            branch: options.branch.clone().unwrap_or_else(|| {
+                get_current_branch(git, &repo_path, dry_run).unwrap_or_default()
+            }),
+            changed,
+        };
+        match template.render(&values) {
+            Ok(_) => {
+                message_values.insert(repo, values);
+            }
+            Err(err) => errors.push(format!("{}: {}", repo.display(), err)),
+        }
+    }
+    if !errors.is_empty() {
+        errors.sort();
+        for error in errors {
+            prntln(&error, MessageType::Error);
+        }
+        prntln("Nothing was committed.", MessageType::Error);
+        std::process::exit(1);
+    }
+    message_values
+}
+
+// Renders the message with {changed} as the amount of files which were actually staged
+fn render_message(
+    git: &GitConfig,
+    repo_path: &str,
+    template: &MessageTemplate,
+    values: &MessageValues,
+    dry_run: bool,
+) -> Result<String, String> {
+    let changed: usize = match dry_run {
+        // Nothing was staged, so the changes found before are the best guess
+        true => values.changed,
+        false => run_cmd_r(
+            create_git_cmd(git, repo_path).args(["diff", "--cached", "--name-only", "-z"]),
+            false,
+        )?
+        .split('\0')
+        .filter(|file: &&str| !file.is_empty())
+        .count(),
+    };
+    template.render(&MessageValues {
+        changed,
+        ..values.clone()
+    })
+}
+
+// git status limited to the changes quick would stage
+fn status_args(options: &QuickOptions) -> Vec<String> {
+    let mut args: Vec<String> = vec!["status".to_string(), "--porcelain".to_string()];
+    if options.tracked_only {
+        args.push("--untracked-files=no".to_string());
+    }
+    if !options.pathspecs.is_empty() {
+        args.push("--".to_string());
+        args.extend(options.pathspecs.iter().cloned());
+    }
+    args
+}
+
+// git add . by default, -u only stages tracked files and pathspecs limit what is staged
+fn add_args(options: &QuickOptions) -> Vec<String> {
+    let mut args: Vec<String> = vec!["add".to_string()];
+    if options.tracked_only {
+        args.push("-u".to_string());
+    }
+    match options.pathspecs.is_empty() {
+        true if !options.tracked_only => args.push(".".to_string()),
+        true => {}
+        false => {
+            args.push("--".to_string());
+            args.extend(options.pathspecs.iter().cloned());
+        }
+    }
+    args
+}
+
+// The changed files quick would stage, parsed from git status --porcelain=v2 -z
+fn changed_files(git: &GitConfig, repo_path: &str, options: &QuickOptions) -> Vec<String> {
+    let mut args: Vec<String> = status_args(options);
+    args[1] = "--porcelain=v2".to_string();
+    args.insert(2, "-z".to_string());
+    let output: String = run_cmd_o(create_git_cmd(git, repo_path).args(args), options.dry_run);
+    let mut files: Vec<String> = Vec::new();
+    let mut entries = output.split('\0');
+    while let Some(entry) = entries.next() {
+        // The path is the last field, renames are followed by the original path
+        let path: Option<&str> = match entry.chars().next() {
+            Some('1') => entry.splitn(9, ' ').nth(8),
+            Some('2') => {
+                entries.next();
+                entry.splitn(10, ' ').nth(9)
+            }
+            Some('u') => entry.splitn(11, ' ').nth(10),
+            Some('?') => entry.get(2..),
+            _ => None,
+        };
+        if let Some(path) = path {
+            files.push(path.to_string());
+        }
+    }
+    files
+}
+
+fn get_remote_url(
+    git: &GitConfig,
+    repo_path: &str,
+    remote: &str,
+    dry_run: bool,
+) -> Result<String, String> {
+    run_cmd_r(
+        create_git_cmd(git, repo_path).args(["config", "--get", &format!("remote.{}.url", remote)]),
         dry_run,
     )
+    .map_err(|_| format!("The remote {} does not exist", remote))
 }
 
-fn get_current_branch(repo_path: &str, dry_run: bool) -> String {
-    run_cmd_o(
-        create_git_cmd(repo_path).args(&["branch", "--show-current"]),
+fn get_current_branch(git: &GitConfig, repo_path: &str, dry_run: bool) -> Result<String, String> {
+    run_cmd_r(
+        create_git_cmd(git, repo_path).args(["branch", "--show-current"]),
         dry_run,
     )
+    .map_err(|err: String| format!("Could not get the current branch: {}", err))
 }
 
-fn select_profile<'a>(config: &'a Config, remote_url: &str) -> &'a Profile {
+// --profile wins, then the profile the repo is located in, then the provider of the remote
+fn select_profile<'a>(config: &'a Config, repo: &Path, remote_url: &str) -> &'a Profile {
+    if let Some(profile) = config.profile_override() {
+        return profile;
+    }
+    if let Some(key) = config.find_profile_by_path(repo) {
+        return config.profiles.get(key).unwrap();
+    }
     let profiles = config.find_profiles_by_provider(remote_url);
     if profiles.len() == 1 {
         config.profiles.get(profiles[0]).unwrap()
@@ -118,57 +395,225 @@ fn select_profile<'a>(config: &'a Config, remote_url: &str) -> &'a Profile {
     }
 }
 
-fn execute_quick_actions(repo_path: &str, profile: &Profile, message: &str, dry_run: bool) {
-    run_cmd_s(
-        create_git_cmd(repo_path).args(&["config", "user.name", &profile.username]),
-        dry_run,
-        true,
-    );
-    run_cmd_s(
-        create_git_cmd(repo_path).args(&["config", "user.email", &profile.email]),
-        dry_run,
-        true,
-    );
-    run_cmd_s(create_git_cmd(repo_path).args(&["add", "."]), dry_run, true);
-    run_cmd_s(
-        create_git_cmd(repo_path).args(&["commit", "-m", message]),
+// Every step returns an error instead of exiting, so one failing repo doesn't stop the others
+fn execute_quick_actions(
+    git: &GitConfig,
+    repo_path: &str,
+    profile: &Profile,
+    message: Option<(&MessageTemplate, &MessageValues)>,
+    add_args: &[String],
+    options: &QuickOptions,
+) -> Result<(), String> {
+    let dry_run: bool = options.dry_run;
+    let remote: &str = &options.push_to;
+    let identity: Vec<(&str, String)> = identity_config(profile);
+    // Either stored in the repo once or passed with -c to the commands creating commits
+    let overrides: Vec<String> = match profile.writegitconfig {
+        true => {
+            for (key, value) in &identity {
+                run_cmd_r(
+                    create_git_cmd(git, repo_path).args(["config", key, value]),
+                    dry_run,
+                )?;
+            }
+            Vec::new()
+        }
+        false => identity
+            .iter()
+            .flat_map(|(key, value)| ["-c".to_string(), format!("{}={}", key, value)])
+            .collect(),
+    };
+    if let Some(branch) = &options.branch {
+        switch_branch(git, repo_path, branch, dry_run)?;
+    }
+    if !add_args.is_empty() {
+        run_cmd_r(create_git_cmd(git, repo_path).args(add_args), dry_run)
+            .map_err(|err: String| format!("Could not stage the changes: {}", err))?;
+    }
+    // Pathspecs can leave nothing to commit, e.g. if only untracked files changed
+    let (_, nothing_staged) = run_cmd_o_soft(
+        create_git_cmd(git, repo_path).args(["diff", "--cached", "--quiet"]),
         dry_run,
-        true,
     );
-    run_cmd_s(
-        create_git_cmd(repo_path).args(&["pull", "--rebase"]),
+    if nothing_staged && !options.amend && !dry_run {
+        prntln(
+            &format!("Nothing staged in {}, skipping.", repo_path),
+            MessageType::Neutral,
+        );
+        return Ok(());
+    }
+    let mut commit_args: Vec<&str> = vec!["commit"];
+    if options.amend {
+        commit_args.push("--amend");
+    }
+    let message: Option<String> = match message {
+        Some((template, values)) => {
+            Some(render_message(git, repo_path, template, values, dry_run)?)
+        }
+        None => None,
+    };
+    match &message {
+        Some(message) => commit_args.extend(["-m", message]),
+        None => commit_args.push("--no-edit"),
+    }
+    run_cmd_r(
+        create_git_cmd(git, repo_path)
+            .args(&overrides)
+            .args(commit_args),
         dry_run,
-        true,
-    );
-    let branch = get_current_branch(repo_path, dry_run);
-    let set_upstream = run_cmd_o(
-        create_git_cmd(repo_path).args(&["ls-remote", "--heads", "origin", &branch]),
+    )
+    .map_err(|err: String| format!("Could not commit: {}", err))?;
+    let branch: String = get_current_branch(git, repo_path, dry_run)?;
+    let on_remote: bool = !run_cmd_r(
+        create_git_cmd(git, repo_path).args(["ls-remote", "--heads", remote, &branch]),
         dry_run,
     )
+    .map_err(|err: String| format!("Could not reach remote {}: {}", remote, err))?
     .is_empty();
+    // An amended commit would be rebased onto its old version, so it is never pulled.
+    // A branch which is not on the remote yet has nothing to pull.
+    if !options.no_pull && !options.amend && on_remote {
+        // Rebasing rewrites the commit, so it needs the identity and signing settings as well.
+        // Changes which were not staged are stashed during the rebase.
+        if let Err(err) = run_cmd_r(
+            create_git_cmd(git, repo_path).args(&overrides).args([
+                "pull",
+                "--rebase",
+                "--autostash",
+                remote,
+                &branch,
+            ]),
+            dry_run,
+        ) {
+            // Don't leave the repo in the middle of a rebase, aborting also restores the stash
+            let aborted: bool = run_cmd_r(
+                create_git_cmd(git, repo_path).args(["rebase", "--abort"]),
+                dry_run,
+            )
+            .is_ok();
+            return Err(format!(
+                "Pull failed{}, the commit was kept locally: {}",
+                if aborted {
+                    " and the rebase was aborted"
+                } else {
+                    ""
+                },
+                err
+            ));
+        }
+    }
 
-    run_cmd_s(
-        create_git_cmd(repo_path).args(create_push_request_args(&branch, set_upstream)),
+    if options.no_push {
+        prntln(
+            &format!("\n{} {} {}", "Committed in", repo_path, "without pushing"),
+            MessageType::Success,
+        );
+        return Ok(());
+    }
+    run_cmd_r(
+        create_git_cmd(git, repo_path).args(create_push_request_args(
+            remote,
+            &branch,
+            !on_remote,
+            options.force_with_lease,
+        )),
         dry_run,
-        true,
-    );
+    )
+    .map_err(|err: String| push_error(&err))?;
 
     prntln(
         &format!(
             "\n{} {} {} {}",
             "Successfully pushed repo into:",
-            get_remote_url(repo_path, dry_run),
+            get_remote_url(git, repo_path, remote, dry_run).unwrap_or_else(|_| remote.to_string()),
             "on branch",
             branch
         ),
         MessageType::Success,
     );
+    Ok(())
+}
+
+// Switches to the branch, creating it from the current commit if it doesn't exist.
+// The changes in the working tree are carried over.
+fn switch_branch(
+    git: &GitConfig,
+    repo_path: &str,
+    branch: &str,
+    dry_run: bool,
+) -> Result<(), String> {
+    let (_, exists) = run_cmd_o_soft(
+        create_git_cmd(git, repo_path).args([
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("refs/heads/{}", branch),
+        ]),
+        dry_run,
+    );
+    let args: Vec<&str> = match exists {
+        true => vec!["switch", branch],
+        false => vec!["switch", "-c", branch],
+    };
+    run_cmd_r(create_git_cmd(git, repo_path).args(args), dry_run)
+        .map(|_| ())
+        .map_err(|err: String| format!("Could not switch to branch {}: {}", branch, err))
 }
 
-fn create_push_request_args(branch: &str, set_upstream: bool) -> Vec<String> {
-    let mut args: Vec<String> = vec!["push".to_string(), "origin".to_string(), branch.to_string()];
+// Explains the common reasons a push is rejected, the commit stays in the local repo
+fn push_error(err: &str) -> String {
+    let hint: &str =
+        if err.contains("protected branch") || err.contains("pre-receive hook declined") {
+            "the branch is protected, push to another one with --branch"
+        } else if err.contains("stale info") {
+            "the remote changed since the last fetch, --force-with-lease did not overwrite it"
+        } else if err.contains("non-fast-forward") || err.contains("fetch first") {
+            "the remote has newer commits, pull them first or use --force-with-lease after --amend"
+        } else {
+            "the commit was kept locally"
+        };
+    // git reports every rejected ref on a line starting with !
+    let rejected: Vec<&str> = err
+        .lines()
+        .map(str::trim)
+        .filter(|line: &&str| line.starts_with('!'))
+        .collect();
+    match rejected.is_empty() {
+        true => format!("Push rejected, {}: {}", hint, err),
+        false => format!("Push rejected, {}: {}", hint, rejected.join(", ")),
+    }
+}
+
+// The git config of the profile identity, signing settings are only set if configured
+fn identity_config(profile: &Profile) -> Vec<(&str, String)> {
+    let mut identity: Vec<(&str, String)> = vec![
+        ("user.name", profile.username.clone()),
+        ("user.email", profile.email.clone()),
+    ];
+    if !profile.signingkey.is_empty() {
+        identity.push(("user.signingkey", profile.signingkey.clone()));
+    }
+    if !profile.signingformat.is_empty() {
+        identity.push(("gpg.format", profile.signingformat.clone()));
+    }
+    if profile.gpgsign {
+        identity.push(("commit.gpgsign", "true".to_string()));
+    }
+    identity
+}
+
+fn create_push_request_args(
+    remote: &str,
+    branch: &str,
+    set_upstream: bool,
+    force_with_lease: bool,
+) -> Vec<String> {
+    let mut args: Vec<String> = vec!["push".to_string(), remote.to_string(), branch.to_string()];
     if set_upstream {
         args.insert(3, "--set-upstream".to_string());
     }
-    return args;
+    if force_with_lease {
+        args.insert(1, "--force-with-lease".to_string());
+    }
+    args
 }
@@ -0,0 +1,17 @@
+use crate::{
+    config::config::Config,
+    utils::helper::{prntln, MessageType},
+};
+
+// Prints every merged value, with origin prefixed by the file or variable it came from
+pub fn show_config(origin: bool, config: &Config) {
+    for (key, value, source) in config.values() {
+        match origin {
+            true => prntln(
+                &format!("{}\t{} = {}", source, key, value),
+                MessageType::Neutral,
+            ),
+            false => prntln(&format!("{} = {}", key, value), MessageType::Neutral),
+        }
+    }
+}
@@ -1,10 +1,22 @@
 use colored::Colorize;
-use dirs::home_dir;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::fs::{self, File};
+use std::fs;
 use std::path::Path;
-use std::{collections::HashMap, path::PathBuf};
-use toml_edit::{value, DocumentMut};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    path::PathBuf,
+};
+use toml_edit::{value, Array, Decor, DocumentMut, Item, Table, Value};
+
+use crate::{
+    config::layers::{config_layers, user_config_path, Layer, JOBS_ENV, PROFILE_ENV},
+    config::schema::{empty_document, migrate, migrate_file},
+    config::token::{is_reference, redact, resolve_token, serialize_redacted},
+    git_api::cache::DEFAULT_CACHE_TTL,
+    utils::layout::{Layout, DEFAULT_LAYOUT},
+};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Profile {
@@ -14,71 +26,460 @@ pub struct Profile {
     pub email: String,
     pub baseaddress: String,
     pub provider: String,
+    // A plain token or a reference like env:NAME, cmd:command, keyring:name or git-credential
+    #[serde(serialize_with = "serialize_redacted")]
     pub token: String,
     pub targetbasepath: String,
+    pub layout: String,
+    // Seconds a cached repo listing is used without asking the provider
+    pub cachettl: u64,
+    // Network settings used for the API and passed to git as http.* config
+    pub proxy: String,
+    pub cabundle: String,
+    pub clientcert: String,
+    pub clientkey: String,
+    pub http1: bool,
+    // GPG key id or SSH public key used for signing, with the format openpgp, ssh or x509
+    pub signingkey: String,
+    pub signingformat: String,
+    // Sign the commits of grgry quick like commit.gpgsign
+    pub gpgsign: bool,
+    // Write the identity into the local git config of the repo instead of -c for the single commit
+    pub writegitconfig: bool,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            active: false,
+            pulloption: String::from("ssh"),
+            username: String::new(),
+            email: String::new(),
+            baseaddress: String::new(),
+            provider: String::new(),
+            token: String::new(),
+            targetbasepath: String::new(),
+            layout: DEFAULT_LAYOUT.to_string(),
+            cachettl: DEFAULT_CACHE_TTL,
+            proxy: String::new(),
+            cabundle: String::new(),
+            clientcert: String::new(),
+            clientkey: String::new(),
+            http1: false,
+            signingkey: String::new(),
+            signingformat: String::new(),
+            gpgsign: false,
+            writegitconfig: true,
+        }
+    }
+}
+
+impl Profile {
+    pub const FIELDS: [&'static str; 19] = [
+        "active",
+        "pulloption",
+        "username",
+        "email",
+        "baseaddress",
+        "provider",
+        "token",
+        "targetbasepath",
+        "layout",
+        "cachettl",
+        "proxy",
+        "cabundle",
+        "clientcert",
+        "clientkey",
+        "http1",
+        "signingkey",
+        "signingformat",
+        "gpgsign",
+        "writegitconfig",
+    ];
+
+    // A profile as loaded from a file without any fields set
+    fn unset() -> Self {
+        Profile {
+            pulloption: String::new(),
+            ..Profile::default()
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Item {
+        match name {
+            "active" => value(self.active),
+            "pulloption" => value(&self.pulloption),
+            "username" => value(&self.username),
+            "email" => value(&self.email),
+            "baseaddress" => value(&self.baseaddress),
+            "provider" => value(&self.provider),
+            "token" => value(&self.token),
+            "targetbasepath" => value(&self.targetbasepath),
+            "layout" => value(&self.layout),
+            "cachettl" => value(self.cachettl as i64),
+            "proxy" => value(&self.proxy),
+            "cabundle" => value(&self.cabundle),
+            "clientcert" => value(&self.clientcert),
+            "clientkey" => value(&self.clientkey),
+            "http1" => value(self.http1),
+            "signingkey" => value(&self.signingkey),
+            "signingformat" => value(&self.signingformat),
+            "gpgsign" => value(self.gpgsign),
+            "writegitconfig" => value(self.writegitconfig),
+            _ => Item::None,
+        }
+    }
+
+    // Sets a field from a config file, values of the wrong type are ignored
+    fn set_field(&mut self, name: &str, item: &Item) -> bool {
+        let text = |field: &mut String| -> bool {
+            item.as_str()
+                .map(|text: &str| *field = text.to_string())
+                .is_some()
+        };
+        match name {
+            "active" => item
+                .as_bool()
+                .map(|flag: bool| self.active = flag)
+                .is_some(),
+            "pulloption" => text(&mut self.pulloption),
+            "username" => text(&mut self.username),
+            "email" => text(&mut self.email),
+            "baseaddress" => text(&mut self.baseaddress),
+            "provider" => text(&mut self.provider),
+            "token" => text(&mut self.token),
+            "targetbasepath" => text(&mut self.targetbasepath),
+            "layout" => text(&mut self.layout),
+            "cachettl" => item
+                .as_integer()
+                .and_then(|ttl: i64| u64::try_from(ttl).ok())
+                .map(|ttl: u64| self.cachettl = ttl)
+                .is_some(),
+            "proxy" => text(&mut self.proxy),
+            "cabundle" => text(&mut self.cabundle),
+            "clientcert" => text(&mut self.clientcert),
+            "clientkey" => text(&mut self.clientkey),
+            "http1" => item.as_bool().map(|flag: bool| self.http1 = flag).is_some(),
+            "signingkey" => text(&mut self.signingkey),
+            "signingformat" => text(&mut self.signingformat),
+            "gpgsign" => item
+                .as_bool()
+                .map(|flag: bool| self.gpgsign = flag)
+                .is_some(),
+            "writegitconfig" => item
+                .as_bool()
+                .map(|flag: bool| self.writegitconfig = flag)
+                .is_some(),
+            _ => false,
+        }
+    }
+
+    // Reads a profile shared by grgry profile export, it is never imported as active and
+    // missing fields get the defaults of grgry profile add
+    pub fn from_table(table: &Table) -> Profile {
+        let mut profile: Profile = Profile::default();
+        for (field, item) in table.iter() {
+            if field != "active" {
+                profile.set_field(field, item);
+            }
+        }
+        profile
+    }
+
+    // The profile without its active state, redacting leaves out plain tokens but keeps references
+    pub fn to_table(&self, redact_secrets: bool) -> Table {
+        let mut table: Table = Table::new();
+        for field in Profile::FIELDS {
+            match field {
+                "active" => continue,
+                "token" if redact_secrets && !is_reference(&self.token) => continue,
+                _ => {
+                    table.insert(field, self.field(field));
+                }
+            }
+        }
+        table
+    }
+
+    // Returns a copy of the profile holding the actual token instead of its reference
+    pub fn with_resolved_token(&self) -> Profile {
+        match resolve_token(&self.token, &self.baseaddress) {
+            Ok(token) => Profile {
+                token,
+                ..self.clone()
+            },
+            Err(err) => {
+                eprintln!("{}", err.red());
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+// The [settings] section, valid for all profiles
+#[derive(Debug, Default, Clone)]
+pub struct Settings {
+    // Default amount of parallel jobs, also set by GRGRY_JOBS
+    pub jobs: Option<usize>,
+    // Default --format of grgry list
+    pub format: Option<String>,
+    // auto, always or never
+    pub color: Option<String>,
+    // Directory names skipped when searching repos for mass, quick and lock
+    pub ignore: Vec<String>,
+    // Regex the subject of grgry quick commits must match
+    pub commitpattern: Option<String>,
+    // Directories whose .grgry.toml may set every setting and profile field
+    pub trusted: Vec<String>,
 }
 
+// What an untrusted .grgry.toml may set. A cloned repo must not be able to send the token to
+// another host, run a command to resolve it or switch the profile.
+const PROJECT_SETTINGS: [&str; 5] = ["jobs", "format", "color", "ignore", "commitpattern"];
+const PROJECT_FIELDS: [&str; 3] = ["layout", "pulloption", "cachettl"];
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
     pub profiles: HashMap<String, Profile>,
     config_file_path: PathBuf,
+    // Set by --profile, GRGRY_PROFILE or the profile setting of a config file
+    #[serde(skip)]
+    profile_override: Option<String>,
+    #[serde(skip)]
+    pub settings: Settings,
+    // Where each value came from, like "user:/home/me/.config/grgry.toml" or "env:GRGRY_JOBS"
+    #[serde(skip)]
+    origins: BTreeMap<String, String>,
+    // The profiles as loaded, to only write changed values of other layers to the user file
+    #[serde(skip)]
+    loaded: HashMap<String, Profile>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Config {
-    // Creates a new Config by loading the profiles from the given file
+    // Creates a new Config by merging the system, user and project config files and the environment
     pub fn new() -> Self {
-        let config_file_path: PathBuf = Self::get_default_config_path();
+        let config_file_path: PathBuf = user_config_path();
         if !config_file_path.exists() {
             Self::create_empty_config_file(&config_file_path);
         }
-        let profiles: HashMap<String, Profile> = Self::load_profiles(&config_file_path);
-        Config {
-            profiles,
+        migrate_file(&config_file_path);
+        let mut config: Config = Config {
+            profiles: HashMap::new(),
             config_file_path,
-        }
-    }
-
-    fn get_default_config_path() -> PathBuf {
-        let mut config_path: PathBuf = home_dir().expect("Failed to get home directory");
-        config_path.push(".config");
-        config_path.push("grgry.toml");
-        config_path
+            profile_override: None,
+            settings: Settings::default(),
+            origins: BTreeMap::new(),
+            loaded: HashMap::new(),
+        };
+        config.load();
+        config
     }
 
     fn create_empty_config_file(config_file_path: &Path) {
         if let Some(parent) = config_file_path.parent() {
             fs::create_dir_all(parent).expect("Failed to create config directory");
         }
-        File::create(config_file_path).expect("Failed to create config file");
+        fs::write(config_file_path, empty_document().to_string())
+            .expect("Failed to create config file");
     }
 
     pub fn reload(&mut self) {
-        self.profiles = Self::load_profiles(&self.config_file_path);
-    }
-
-    fn load_profiles<P: AsRef<Path>>(config_file_path: P) -> HashMap<String, Profile> {
-        let toml_content: String =
-            fs::read_to_string(config_file_path).expect("Failed to read file");
-        let doc: DocumentMut = toml_content
-            .parse::<DocumentMut>()
-            .expect("Failed to parse TOML");
-
-        //TODO on load check if not empty (VALID)
-        let mut profiles: HashMap<String, Profile> = HashMap::new();
-        for (key, value) in doc.iter() {
-            let profile: Profile = Profile {
-                active: value["active"].as_bool().unwrap_or(false),
-                pulloption: value["pulloption"].as_str().unwrap_or("").to_string(),
-                username: value["username"].as_str().unwrap_or("").to_string(),
-                email: value["email"].as_str().unwrap_or("").to_string(),
-                baseaddress: value["baseaddress"].as_str().unwrap_or("").to_string(),
-                targetbasepath: value["targetbasepath"].as_str().unwrap_or("").to_string(),
-                provider: value["provider"].as_str().unwrap_or("").to_string(),
-                token: value["token"].as_str().unwrap_or("").to_string(),
-            };
-            profiles.insert(key.to_string(), profile);
+        self.profiles.clear();
+        self.origins.clear();
+        self.profile_override = None;
+        self.settings = Settings::default();
+        self.load();
+    }
+
+    fn load(&mut self) {
+        for layer in config_layers(&self.config_file_path) {
+            self.load_layer(&layer);
+        }
+        if let Ok(profile) = env::var(PROFILE_ENV) {
+            self.set_setting("profile", &value(profile), &format!("env:{}", PROFILE_ENV));
+        }
+        if let Ok(jobs) = env::var(JOBS_ENV) {
+            let item: Item = jobs.trim().parse::<i64>().map(value).unwrap_or(value(jobs));
+            self.set_setting("jobs", &item, &format!("env:{}", JOBS_ENV));
         }
-        profiles
+        self.loaded = self.profiles.clone();
+    }
+
+    // Later layers override single settings and fields of the profiles of earlier layers
+    fn load_layer(&mut self, layer: &Layer) {
+        let origin: String = format!("{}:{}", layer.origin, layer.path.display());
+        // Old system or project files are read as if they were migrated, only the user file is rewritten
+        let doc: DocumentMut = migrate(&Self::read_document(&layer.path), &layer.path);
+        let restricted: bool = layer.origin == "project" && !self.is_trusted(&layer.path);
+        let mut ignored: Vec<String> = Vec::new();
+
+        if let Some(settings) = doc.get("settings").and_then(Item::as_table) {
+            for (key, item) in settings.iter() {
+                if restricted && !PROJECT_SETTINGS.contains(&key) {
+                    ignored.push(format!("settings.{}", key));
+                    continue;
+                }
+                self.set_setting(key, item, &origin);
+            }
+        }
+        // Missing fields stay empty instead of failing, grgry profile validate reports them
+        if let Some(profiles) = doc.get("profiles").and_then(Item::as_table) {
+            for (key, item) in profiles.iter() {
+                let Some(table) = item.as_table() else {
+                    continue;
+                };
+                // An untrusted project file can only adjust the profiles of the user
+                if restricted && !self.profiles.contains_key(key) {
+                    ignored.push(profile_key(key, "*"));
+                    continue;
+                }
+                let profile: &mut Profile = self
+                    .profiles
+                    .entry(key.to_string())
+                    .or_insert_with(Profile::unset);
+                for (field, item) in table.iter() {
+                    if restricted && !PROJECT_FIELDS.contains(&field) {
+                        ignored.push(profile_key(key, field));
+                        continue;
+                    }
+                    // A layout leaving the targetbasepath would clone and pull anywhere
+                    if restricted && field == "layout" {
+                        if let Some(Err(err)) = item.as_str().map(Layout::new) {
+                            eprintln!(
+                                "{}",
+                                format!(
+                                    "Ignoring {} from the untrusted {}: {}",
+                                    profile_key(key, field),
+                                    layer.path.display(),
+                                    err
+                                )
+                                .yellow()
+                            );
+                            continue;
+                        }
+                    }
+                    if profile.set_field(field, item) {
+                        self.origins.insert(profile_key(key, field), origin.clone());
+                    }
+                }
+            }
+        }
+        if !ignored.is_empty() {
+            eprintln!(
+                "{}",
+                format!(
+                    "Ignoring {} from the untrusted {}, add {} to settings.trusted of {} to allow them",
+                    ignored.join(", "),
+                    layer.path.display(),
+                    layer.path.parent().unwrap_or(&layer.path).display(),
+                    self.config_file_path.display()
+                )
+                .yellow()
+            );
+        }
+    }
+
+    // A project file is trusted if its directory is listed in settings.trusted
+    fn is_trusted(&self, path: &Path) -> bool {
+        let Some(dir) = path.parent().and_then(|dir: &Path| dir.canonicalize().ok()) else {
+            return false;
+        };
+        self.settings.trusted.iter().any(|trusted: &String| {
+            Path::new(trusted)
+                .canonicalize()
+                .map(|trusted: PathBuf| trusted == dir)
+                .unwrap_or(false)
+        })
+    }
+
+    // Invalid settings are skipped with a warning so a typo does not break every command
+    fn set_setting(&mut self, key: &str, item: &Item, origin: &str) {
+        let valid: bool = match key {
+            "profile" => item
+                .as_str()
+                .map(|profile: &str| self.profile_override = Some(profile.to_string()))
+                .is_some(),
+            "jobs" => item
+                .as_integer()
+                .and_then(|jobs: i64| usize::try_from(jobs).ok())
+                .filter(|jobs: &usize| *jobs > 0)
+                .map(|jobs: usize| self.settings.jobs = Some(jobs))
+                .is_some(),
+            "format" => item
+                .as_str()
+                .filter(|format: &&str| ["table", "json", "url"].contains(format))
+                .map(|format: &str| self.settings.format = Some(format.to_string()))
+                .is_some(),
+            "color" => item
+                .as_str()
+                .filter(|color: &&str| ["auto", "always", "never"].contains(color))
+                .map(|color: &str| self.settings.color = Some(color.to_string()))
+                .is_some(),
+            "commitpattern" => item
+                .as_str()
+                .filter(|pattern: &&str| Regex::new(pattern).is_ok())
+                .map(|pattern: &str| self.settings.commitpattern = Some(pattern.to_string()))
+                .is_some(),
+            "ignore" => item
+                .as_array()
+                .map(|names| {
+                    names
+                        .iter()
+                        .filter_map(|name| name.as_str().map(str::to_string))
+                        .collect()
+                })
+                .map(|names: Vec<String>| self.settings.ignore = names)
+                .is_some(),
+            "trusted" => item
+                .as_array()
+                .map(|dirs| {
+                    dirs.iter()
+                        .filter_map(|dir| dir.as_str().map(str::to_string))
+                        .collect()
+                })
+                .map(|dirs: Vec<String>| self.settings.trusted = dirs)
+                .is_some(),
+            _ => {
+                eprintln!(
+                    "{}",
+                    format!("Ignoring unknown setting {} from {}", key, origin).yellow()
+                );
+                return;
+            }
+        };
+        match valid {
+            true => {
+                self.origins
+                    .insert(format!("settings.{}", key), origin.to_string());
+            }
+            false => eprintln!(
+                "{}",
+                format!(
+                    "Ignoring invalid setting {} = {} from {}",
+                    key,
+                    item.to_string().trim(),
+                    origin
+                )
+                .yellow()
+            ),
+        }
+    }
+
+    fn read_document(path: &Path) -> DocumentMut {
+        let toml_content: String = fs::read_to_string(path).expect("Failed to read file");
+        toml_content.parse::<DocumentMut>().unwrap_or_else(|err| {
+            eprintln!(
+                "{}",
+                format!("Failed to parse {}: {}", path.display(), err).red()
+            );
+            std::process::exit(1);
+        })
     }
 
     // Activates the profile specified by `choice`
@@ -95,52 +496,222 @@ impl Config {
         self.save_config();
     }
 
-    // Saves the current state of profiles back to the config file
+    // Renames the profile, a profile setting of the user file is changed along with it
+    pub fn rename_profile(&mut self, name: &str, new_name: &str) {
+        let profile: Profile = self.profiles.remove(name).expect("Profile does not exist");
+        self.profiles.insert(new_name.to_string(), profile);
+        self.save_config();
+        let mut doc: DocumentMut = Self::read_document(&self.config_file_path);
+        if let Some(profile) = doc
+            .get_mut("settings")
+            .and_then(|settings: &mut Item| settings.get_mut("profile"))
+            .and_then(Item::as_value_mut)
+            .filter(|profile: &&mut Value| profile.as_str() == Some(name))
+        {
+            let decor: Decor = profile.decor().clone();
+            *profile = Value::from(new_name);
+            *profile.decor_mut() = decor;
+            fs::write(&self.config_file_path, doc.to_string()).expect("Failed to write file");
+        }
+        if self.profile_override.as_deref() != Some(name) {
+            return;
+        }
+        let origin: String = self.origin_or_default("settings.profile");
+        if origin != format!("user:{}", self.config_file_path.display()) {
+            eprintln!(
+                "{}",
+                format!(
+                    "The profile setting of {} still uses {}, change it to {}",
+                    origin, name, new_name
+                )
+                .yellow()
+            );
+        }
+        self.profile_override = Some(new_name.to_string());
+    }
+
+    // Saves the changed values of the profiles to the user config file
     pub fn save_config(&self) {
-        let toml_content: String =
-            fs::read_to_string(&self.config_file_path).expect("Failed to read file");
-        let mut doc: DocumentMut = toml_content
-            .parse::<DocumentMut>()
-            .expect("Failed to parse TOML");
+        // The user file would only get the changed fields, so outside of the project the
+        // profile would be incomplete
+        for key in self.loaded.keys() {
+            let changed: bool = match self.profiles.get(key) {
+                Some(profile) => Profile::FIELDS.iter().any(|field: &&str| {
+                    self.loaded[key].field(field).to_string() != profile.field(field).to_string()
+                }),
+                None => true,
+            };
+            if changed && self.is_project_only(key) {
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Profile {} is only defined in the project config {}, change it there",
+                        key,
+                        self.origin_or_default(&profile_key(key, "provider"))
+                            .trim_start_matches("project:")
+                    )
+                    .red()
+                );
+                std::process::exit(1);
+            }
+        }
+        let mut doc: DocumentMut = Self::read_document(&self.config_file_path);
+        let mut profiles: Table = doc
+            .get("profiles")
+            .and_then(Item::as_table)
+            .cloned()
+            .unwrap_or_default();
+        profiles.set_implicit(true);
 
         // Remove profiles that are no longer in the HashMap
-        let existing_keys: Vec<String> = doc.iter().map(|(key, _)| key.to_string()).collect();
+        let existing_keys: Vec<String> = profiles.iter().map(|(key, _)| key.to_string()).collect();
         for key in existing_keys {
             if !self.profiles.contains_key(&key) {
-                doc.remove(&key);
+                profiles.remove(&key);
             }
         }
 
         // Update or create profiles in the TOML document
         for (key, profile) in self.profiles.iter() {
-            if let Some(profile_table) = doc[key].as_table_mut() {
-                profile_table["active"] = value(profile.active);
-                profile_table["pulloption"] = value(&profile.pulloption);
-                profile_table["username"] = value(&profile.username);
-                profile_table["email"] = value(&profile.email);
-                profile_table["baseaddress"] = value(&profile.baseaddress);
-                profile_table["targetbasepath"] = value(&profile.targetbasepath);
-                profile_table["provider"] = value(&profile.provider);
-                profile_table["token"] = value(&profile.token);
-            } else {
-                // If the profile does not exist, create it
-                let mut new_profile = toml_edit::Table::new();
-                new_profile["active"] = value(profile.active);
-                new_profile["pulloption"] = value(&profile.pulloption);
-                new_profile["username"] = value(&profile.username);
-                new_profile["email"] = value(&profile.email);
-                new_profile["baseaddress"] = value(&profile.baseaddress);
-                new_profile["targetbasepath"] = value(&profile.targetbasepath);
-                new_profile["provider"] = value(&profile.provider);
-                new_profile["token"] = value(&profile.token);
-                doc[key] = toml_edit::Item::Table(new_profile);
+            let mut profile_table: Table = profiles
+                .get(key)
+                .and_then(Item::as_table)
+                .cloned()
+                .unwrap_or_default();
+            for field in Profile::FIELDS {
+                let current: Item = profile.field(field);
+                let changed: bool = match self.loaded.get(key) {
+                    Some(loaded) => loaded.field(field).to_string() != current.to_string(),
+                    None => true,
+                };
+                // Unchanged values are already in the user file or come from another layer
+                if !changed {
+                    continue;
+                }
+                // Keep comments next to the value
+                let decor: Option<Decor> = profile_table
+                    .get(field)
+                    .and_then(Item::as_value)
+                    .map(|old: &Value| old.decor().clone());
+                profile_table[field] = current;
+                if let (Some(decor), Some(new)) = (decor, profile_table[field].as_value_mut()) {
+                    *new.decor_mut() = decor;
+                }
+            }
+            if !profile_table.is_empty() {
+                profiles.insert(key, Item::Table(profile_table));
             }
         }
+        doc["profiles"] = Item::Table(profiles);
 
         fs::write(&self.config_file_path, doc.to_string()).expect("Failed to write file");
     }
 
+    // A profile which no system or user config defines a field of
+    fn is_project_only(&self, name: &str) -> bool {
+        let prefix: String = profile_key(name, "");
+        let origins: Vec<&String> = self
+            .origins
+            .iter()
+            .filter(|(key, _)| key.starts_with(&prefix))
+            .map(|(_, origin)| origin)
+            .collect();
+        !origins.is_empty()
+            && origins
+                .iter()
+                .all(|origin: &&String| origin.starts_with("project:"))
+    }
+
+    pub fn origin(&self, key: &str) -> Option<&str> {
+        self.origins.get(key).map(String::as_str)
+    }
+
+    // All values as key, value and origin sorted by key, tokens are redacted
+    pub fn values(&self) -> Vec<(String, String, String)> {
+        let mut values: Vec<(String, String, String)> = Vec::new();
+        let ignore: Option<Item> = match self.settings.ignore.is_empty() {
+            true => None,
+            false => Some(value(Array::from_iter(self.settings.ignore.iter()))),
+        };
+        let trusted: Option<Item> = match self.settings.trusted.is_empty() {
+            true => None,
+            false => Some(value(Array::from_iter(self.settings.trusted.iter()))),
+        };
+        for (key, item) in [
+            ("profile", self.profile_override.as_ref().map(value)),
+            (
+                "jobs",
+                self.settings.jobs.map(|jobs: usize| value(jobs as i64)),
+            ),
+            ("format", self.settings.format.as_ref().map(value)),
+            ("color", self.settings.color.as_ref().map(value)),
+            ("ignore", ignore),
+            (
+                "commitpattern",
+                self.settings.commitpattern.as_ref().map(value),
+            ),
+            ("trusted", trusted),
+        ] {
+            if let Some(item) = item {
+                let key: String = format!("settings.{}", key);
+                let origin: String = self.origin_or_default(&key);
+                values.push((key, item.to_string().trim().to_string(), origin));
+            }
+        }
+        let mut names: Vec<&String> = self.profiles.keys().collect();
+        names.sort();
+        for name in names {
+            let profile: &Profile = &self.profiles[name];
+            for field in Profile::FIELDS {
+                let key: String = profile_key(name, field);
+                let item: Item = match field {
+                    "token" => value(redact(&profile.token)),
+                    _ => profile.field(field),
+                };
+                let origin: String = self.origin_or_default(&key);
+                values.push((key, item.to_string().trim().to_string(), origin));
+            }
+        }
+        values
+    }
+
+    fn origin_or_default(&self, key: &str) -> String {
+        self.origin(key).unwrap_or("default").to_string()
+    }
+
+    // Uses the profile for this run only, the active profile in the file stays the same
+    pub fn use_profile(&mut self, name: &str) {
+        if !self.profiles.contains_key(name) {
+            eprintln!("{}", format!("Profile {} does not exist", name).red());
+            std::process::exit(1);
+        }
+        self.set_setting("profile", &value(name), "flag:--profile");
+    }
+
+    pub fn profile_override(&self) -> Option<&Profile> {
+        self.profile_override
+            .as_ref()
+            .map(|name: &String| match self.profiles.get(name) {
+                Some(profile) => profile,
+                None => {
+                    eprintln!(
+                        "{}",
+                        format!(
+                            "Profile {} set by {} does not exist",
+                            name,
+                            self.origin_or_default("settings.profile")
+                        )
+                        .red()
+                    );
+                    std::process::exit(1);
+                }
+            })
+    }
+
     pub fn active_profile(&self) -> &Profile {
+        if let Some(profile) = self.profile_override() {
+            return profile;
+        }
         match self
             .profiles
             .values()
@@ -157,6 +728,35 @@ impl Config {
         }
     }
 
+    // The profile whose network settings git uses in the repo, --profile or the one it is located in
+    pub fn profile_for_repo(&self, repo: &Path) -> Option<&Profile> {
+        self.profile_override().or_else(|| {
+            self.find_profile_by_path(repo)
+                .and_then(|key: &str| self.profiles.get(key))
+        })
+    }
+
+    // The profile whose targetbasepath contains the path, the deepest one if they are nested
+    pub fn find_profile_by_path(&self, path: &Path) -> Option<&str> {
+        // The repo of grgry restore might not exist yet
+        let path: PathBuf = path
+            .canonicalize()
+            .or_else(|_| std::path::absolute(path))
+            .unwrap_or(path.to_path_buf());
+        self.profiles
+            .iter()
+            .filter(|(_, profile)| !profile.targetbasepath.is_empty())
+            .filter_map(|(key, profile)| {
+                let base: PathBuf = Path::new(&profile.targetbasepath)
+                    .canonicalize()
+                    .unwrap_or(PathBuf::from(&profile.targetbasepath));
+                path.starts_with(&base)
+                    .then_some((key.as_str(), base.components().count()))
+            })
+            .max_by_key(|(_, depth)| *depth)
+            .map(|(key, _)| key)
+    }
+
     pub fn find_profiles_by_provider(&self, remote_origin_url: &str) -> Vec<&str> {
         self.profiles
             .iter()
@@ -170,3 +770,15 @@ impl Config {
             .collect()
     }
 }
+
+// Profile names with spaces like "Github Profile" need quotes to be a valid key
+fn profile_key(name: &str, field: &str) -> String {
+    let name: String = match name
+        .chars()
+        .all(|c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        true => name.to_string(),
+        false => format!("{:?}", name),
+    };
+    format!("profiles.{}.{}", name, field)
+}
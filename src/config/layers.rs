@@ -0,0 +1,72 @@
+use std::{
+    env::{self, current_dir},
+    path::{Path, PathBuf},
+};
+
+use dirs::home_dir;
+
+pub const PROJECT_CONFIG_FILE: &str = ".grgry.toml";
+pub const PROFILE_ENV: &str = "GRGRY_PROFILE";
+pub const JOBS_ENV: &str = "GRGRY_JOBS";
+
+// A config file and the name it is shown with by grgry config show --origin
+pub struct Layer {
+    pub origin: String,
+    pub path: PathBuf,
+}
+
+// The file grgry writes profiles to, $XDG_CONFIG_HOME/grgry.toml or ~/.config/grgry.toml.
+// An existing ~/.config/grgry.toml is still used as long as XDG_CONFIG_HOME has no grgry.toml.
+pub fn user_config_path() -> PathBuf {
+    let mut legacy_path: PathBuf = home_dir().expect("Failed to get home directory");
+    legacy_path.push(".config");
+    legacy_path.push("grgry.toml");
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(path) if !path.is_empty() => {
+            let config_path: PathBuf = PathBuf::from(path).join("grgry.toml");
+            match !config_path.exists() && legacy_path.is_file() {
+                true => legacy_path,
+                false => config_path,
+            }
+        }
+        _ => legacy_path,
+    }
+}
+
+fn system_config_path() -> PathBuf {
+    let root: PathBuf = match cfg!(windows) {
+        true => PathBuf::from(env::var_os("PROGRAMDATA").unwrap_or("C:\\ProgramData".into())),
+        false => PathBuf::from("/etc"),
+    };
+    root.join("grgry").join("grgry.toml")
+}
+
+// The nearest .grgry.toml in the current directory or one of its parents
+fn project_config_path() -> Option<PathBuf> {
+    let cwd: PathBuf = current_dir().ok()?;
+    cwd.ancestors()
+        .map(|dir: &Path| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path: &PathBuf| path.is_file())
+}
+
+// The existing config files from the lowest to the highest priority
+pub fn config_layers(user_path: &Path) -> Vec<Layer> {
+    let mut layers: Vec<Layer> = vec![
+        Layer {
+            origin: "system".to_string(),
+            path: system_config_path(),
+        },
+        Layer {
+            origin: "user".to_string(),
+            path: user_path.to_path_buf(),
+        },
+    ];
+    if let Some(path) = project_config_path() {
+        layers.push(Layer {
+            origin: "project".to_string(),
+            path,
+        });
+    }
+    layers.retain(|layer: &Layer| layer.path.is_file());
+    layers
+}
@@ -1 +1,5 @@
+#[allow(clippy::module_inception)]
 pub mod config;
+pub mod layers;
+pub mod schema;
+pub mod token;
@@ -0,0 +1,201 @@
+use std::{fs, path::Path};
+
+use colored::Colorize;
+use toml_edit::{value, DocumentMut, Item, Key, Table};
+
+// Version 1 had every top-level table as profile, version 2 has [settings] and [profiles.*]
+pub const CONFIG_VERSION: i64 = 2;
+
+pub fn version(doc: &DocumentMut) -> i64 {
+    doc.get("version").and_then(Item::as_integer).unwrap_or(1)
+}
+
+pub fn empty_document() -> DocumentMut {
+    let mut doc: DocumentMut = DocumentMut::new();
+    doc["version"] = value(CONFIG_VERSION);
+    doc
+}
+
+// Converts a document of any known version to the current one, only in memory
+pub fn migrate(doc: &DocumentMut, path: &Path) -> DocumentMut {
+    match version(doc) {
+        CONFIG_VERSION => doc.clone(),
+        1 => migrate_flat(doc),
+        newer => {
+            eprintln!(
+                "{}",
+                format!(
+                    "{} has config version {}, this grgry only knows version {}. Please update grgry.",
+                    path.display(),
+                    newer,
+                    CONFIG_VERSION
+                )
+                .red()
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+// Rewrites an old config file in the current format and keeps the old one as .bak
+pub fn migrate_file(path: &Path) {
+    let toml_content: String = fs::read_to_string(path).expect("Failed to read file");
+    let doc: DocumentMut = match toml_content.parse::<DocumentMut>() {
+        Ok(doc) => doc,
+        // Reported when the file is loaded
+        Err(_) => return,
+    };
+    if version(&doc) == CONFIG_VERSION {
+        return;
+    }
+    let migrated: DocumentMut = migrate(&doc, path);
+    if !doc.is_empty() {
+        let mut backup: std::ffi::OsString = path.as_os_str().to_owned();
+        backup.push(".bak");
+        fs::copy(path, &backup).expect("Failed to back up config file");
+        eprintln!(
+            "{}",
+            format!(
+                "Migrated {} to config version {}, the old file is kept as {}",
+                path.display(),
+                CONFIG_VERSION,
+                Path::new(&backup).display()
+            )
+            .yellow()
+        );
+    }
+    fs::write(path, migrated.to_string()).expect("Failed to write file");
+}
+
+fn migrate_flat(doc: &DocumentMut) -> DocumentMut {
+    let mut migrated: DocumentMut = empty_document();
+    let mut settings: Table = Table::new();
+    let mut profiles: Table = Table::new();
+    profiles.set_implicit(true);
+    for (name, item) in doc.iter() {
+        // The key keeps its decor, which holds the comments above it
+        let key: &Key = doc.key(name).expect("Iterated key exists");
+        match item.as_table() {
+            Some(profile) => {
+                let mut profile: Table = profile.clone();
+                profile.set_position(profiles.len() + 2);
+                profiles.insert_formatted(key, Item::Table(profile));
+            }
+            // The top-level profile and jobs keys of version 1 are settings now
+            None => {
+                settings.insert_formatted(key, item.clone());
+            }
+        }
+    }
+    settings.set_position(1);
+    if !settings.is_empty() {
+        migrated["settings"] = Item::Table(settings);
+    }
+    migrated["profiles"] = Item::Table(profiles);
+    migrated.set_trailing(doc.trailing().clone());
+    migrated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const FLAT: &str = r#"# grgry config
+jobs = 4 # parallel jobs
+unknownsetting = "kept"
+
+# work profile
+[work]
+active = true
+provider = "gitlab"
+baseaddress = "https://gitlab.example.com" # self hosted
+unknownfield = 1
+
+[private]
+active = false
+provider = "github"
+# end of file
+"#;
+
+    fn temp_file(name: &str, content: &str) -> PathBuf {
+        // One directory per test, as the tests run in parallel
+        let dir: PathBuf =
+            std::env::temp_dir().join(format!("grgry-schema-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let path: PathBuf = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    #[test]
+    fn migrates_flat_profiles_and_settings() {
+        let migrated: DocumentMut = migrate_flat(&FLAT.parse::<DocumentMut>().unwrap());
+        assert_eq!(version(&migrated), CONFIG_VERSION);
+        assert_eq!(migrated["settings"]["jobs"].as_integer(), Some(4));
+        assert_eq!(
+            migrated["profiles"]["work"]["baseaddress"].as_str(),
+            Some("https://gitlab.example.com")
+        );
+        assert_eq!(
+            migrated["profiles"]["private"]["provider"].as_str(),
+            Some("github")
+        );
+        assert!(migrated.get("work").is_none());
+    }
+
+    #[test]
+    fn keeps_comments_and_unknown_keys() {
+        let migrated: String = migrate_flat(&FLAT.parse::<DocumentMut>().unwrap()).to_string();
+        for kept in [
+            "# grgry config",
+            "# parallel jobs",
+            "unknownsetting = \"kept\"",
+            "# work profile",
+            "# self hosted",
+            "unknownfield = 1",
+            "# end of file",
+        ] {
+            assert!(migrated.contains(kept), "{} missing in\n{}", kept, migrated);
+        }
+        // The profiles stay in the order of the file
+        assert!(migrated.find("[profiles.work]") < migrated.find("[profiles.private]"));
+    }
+
+    #[test]
+    fn migrated_document_is_read_again_unchanged() {
+        let migrated: DocumentMut = migrate_flat(&FLAT.parse::<DocumentMut>().unwrap());
+        let reparsed: DocumentMut = migrated.to_string().parse::<DocumentMut>().unwrap();
+        assert_eq!(
+            migrate(&reparsed, Path::new("grgry.toml")).to_string(),
+            migrated.to_string()
+        );
+    }
+
+    #[test]
+    fn migrates_file_and_keeps_backup() {
+        let path: PathBuf = temp_file("flat.toml", FLAT);
+        migrate_file(&path);
+        let migrated: String = fs::read_to_string(&path).unwrap();
+        assert!(migrated.starts_with("version = 2"));
+        assert!(migrated.contains("[profiles.work]"));
+        assert_eq!(
+            fs::read_to_string(path.with_extension("toml.bak")).unwrap(),
+            FLAT
+        );
+        // A second run leaves the migrated file alone
+        migrate_file(&path);
+        assert_eq!(fs::read_to_string(&path).unwrap(), migrated);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn leaves_current_file_alone() {
+        let content: &str = "version = 2 # current\n\n[profiles.work]\nprovider = \"gitlab\"\n";
+        let path: PathBuf = temp_file("current.toml", content);
+        migrate_file(&path);
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        assert!(!path.with_extension("toml.bak").exists());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
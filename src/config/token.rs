@@ -0,0 +1,188 @@
+use std::{
+    env,
+    io::Write,
+    process::{Child, Command, Output, Stdio},
+};
+
+use reqwest::Url;
+use serde::Serializer;
+
+const ENV_PREFIX: &str = "env:";
+const CMD_PREFIX: &str = "cmd:";
+const KEYRING_PREFIX: &str = "keyring:";
+const GIT_CREDENTIAL: &str = "git-credential";
+
+// A token like env:GITLAB_TOKEN only references the secret, so it is not hidden
+pub fn is_reference(token: &str) -> bool {
+    token.starts_with(ENV_PREFIX)
+        || token.starts_with(CMD_PREFIX)
+        || token.starts_with(KEYRING_PREFIX)
+        || token.starts_with(GIT_CREDENTIAL)
+}
+
+// Describes the secret of this machine or the command a shared token would use, the result
+// is sent to the baseaddress. None if only the token itself or the credentials of the
+// baseaddress host are used.
+pub fn local_token_source(token: &str, baseaddress: &str) -> Option<String> {
+    if let Some(command) = token.strip_prefix(CMD_PREFIX) {
+        return Some(format!("runs the command {}", command));
+    }
+    if let Some(account) = token.strip_prefix(KEYRING_PREFIX) {
+        return Some(format!("reads the keyring entry {}", account));
+    }
+    if let Some(variable) = token.strip_prefix(ENV_PREFIX) {
+        return Some(format!("reads the environment variable {}", variable));
+    }
+    let host: &str = token.strip_prefix(GIT_CREDENTIAL)?.strip_prefix(':')?;
+    match credential_host(baseaddress) {
+        Ok(base_host) if base_host == host => None,
+        _ => Some(format!("uses the git credentials of {}", host)),
+    }
+}
+
+pub fn redact(token: &str) -> String {
+    if token.is_empty() || is_reference(token) {
+        token.to_string()
+    } else {
+        "<redacted>".to_string()
+    }
+}
+
+// Used by serde, so a token never ends up in the output of grgry profile show
+pub fn serialize_redacted<S: Serializer>(token: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&redact(token))
+}
+
+// Resolves the token of a profile from its source, a plain token is returned as it is
+pub fn resolve_token(token: &str, baseaddress: &str) -> Result<String, String> {
+    if let Some(variable) = token.strip_prefix(ENV_PREFIX) {
+        return env::var(variable)
+            .map(|value: String| value.trim().to_string())
+            .map_err(|_| format!("Environment variable {} is not set", variable));
+    }
+    if let Some(command) = token.strip_prefix(CMD_PREFIX) {
+        return run_token_command(shell_command(command), None)
+            .map_err(|err: String| format!("Token command {} failed: {}", command, err));
+    }
+    if let Some(account) = token.strip_prefix(KEYRING_PREFIX) {
+        let mut command: Command = Command::new("secret-tool");
+        command.args(["lookup", "service", "grgry", "account", account]);
+        return run_token_command(command, None)
+            .map_err(|err: String| format!("Token {} not found in the keyring: {}", account, err));
+    }
+    if let Some(host) = token.strip_prefix(GIT_CREDENTIAL) {
+        let host: String = match host.strip_prefix(':') {
+            Some(host) => host.to_string(),
+            None => credential_host(baseaddress)?,
+        };
+        return git_credential_fill(&host);
+    }
+    Ok(token.to_string())
+}
+
+// The api host of github.com has no credentials stored, the web host has
+fn credential_host(baseaddress: &str) -> Result<String, String> {
+    let url: Url = Url::parse(baseaddress)
+        .map_err(|err| format!("Base address {} is invalid: {}", baseaddress, err))?;
+    let host: &str = url
+        .host_str()
+        .ok_or(format!("Base address {} has no host", baseaddress))?;
+    Ok(host.strip_prefix("api.").unwrap_or(host).to_string())
+}
+
+fn git_credential_fill(host: &str) -> Result<String, String> {
+    let mut command: Command = Command::new("git");
+    command
+        .args(["credential", "fill"])
+        // Never ask interactively, the helper either knows the token or not
+        .env("GIT_TERMINAL_PROMPT", "0");
+    let output: String =
+        run_token_command(command, Some(format!("protocol=https\nhost={}\n\n", host)))
+            .map_err(|err: String| format!("git credential fill for {} failed: {}", host, err))?;
+    output
+        .lines()
+        .find_map(|line: &str| line.strip_prefix("password="))
+        .map(str::to_string)
+        .ok_or(format!(
+            "git credential fill returned no password for {}",
+            host
+        ))
+}
+
+fn shell_command(command: &str) -> Command {
+    let mut shell: Command = if cfg!(windows) {
+        let mut shell: Command = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell: Command = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+fn run_token_command(mut command: Command, input: Option<String>) -> Result<String, String> {
+    command
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child: Child = command.spawn().map_err(|err| err.to_string())?;
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        stdin
+            .write_all(input.as_bytes())
+            .map_err(|err| err.to_string())?;
+    }
+    let output: Output = child.wait_with_output().map_err(|err| err.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    let token: String = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if token.is_empty() {
+        return Err("no token returned".to_string());
+    }
+    Ok(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASEADDRESS: &str = "https://gitlab.example.com";
+
+    #[test]
+    fn plain_tokens_use_nothing_of_this_machine() {
+        assert_eq!(local_token_source("", BASEADDRESS), None);
+        assert_eq!(local_token_source("glpat-secret", BASEADDRESS), None);
+        assert_eq!(local_token_source("git-credential", BASEADDRESS), None);
+    }
+
+    #[test]
+    fn describes_commands_keyring_and_environment() {
+        assert_eq!(
+            local_token_source("cmd:pass show gitlab", BASEADDRESS),
+            Some("runs the command pass show gitlab".to_string())
+        );
+        assert!(local_token_source("keyring:work", BASEADDRESS).is_some());
+        assert!(local_token_source("env:GITLAB_TOKEN", BASEADDRESS).is_some());
+    }
+
+    #[test]
+    fn git_credentials_of_other_hosts_need_confirmation() {
+        assert_eq!(
+            local_token_source("git-credential:gitlab.example.com", BASEADDRESS),
+            None
+        );
+        assert_eq!(
+            local_token_source("git-credential:github.com", "https://api.github.com"),
+            None
+        );
+        assert!(local_token_source("git-credential:github.com", BASEADDRESS).is_some());
+        assert!(local_token_source("git-credential:github.com", "not a url").is_some());
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use colored::Colorize;
+use reqwest::{header::HeaderValue, Client, RequestBuilder, Response, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::config::Profile,
+    git_api::git_providers::{
+        parse_repos, send_with_retries, to_header_map, ApiError, ListOptions, Repo, MAX_RETRIES,
+    },
+};
+
+pub const DEFAULT_CACHE_TTL: u64 = 3600;
+// Only these headers are needed to page through a cached listing
+const CACHED_HEADERS: [&str; 3] = ["link", "x-total-pages", "x-next-page"];
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+struct CacheFile {
+    pages: BTreeMap<String, CachedPage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct CachedPage {
+    etag: Option<String>,
+    fetched_at: u64,
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+// On-disk cache of the listing pages of one collection, revalidated with ETag / If-None-Match
+pub struct ListingCache {
+    path: Option<PathBuf>,
+    ttl: u64,
+    refresh: bool,
+    previous: BTreeMap<String, CachedPage>,
+    current: Mutex<BTreeMap<String, CachedPage>>,
+}
+
+impl ListingCache {
+    pub fn load(
+        active_profile: &Profile,
+        collection_name: &str,
+        options: &ListOptions,
+    ) -> Arc<Self> {
+        // Every combination of options which changes the listing gets its own file
+        let mut file_name: String = format!(
+            "{}_{}",
+            if options.user { "user" } else { "group" },
+            escape(collection_name)
+        );
+        if !options.affiliation.is_empty() {
+            file_name.push_str(&format!("_affiliation-{}", options.affiliation.join(",")));
+        }
+        if let Some(visibility) = &options.visibility {
+            file_name.push_str(&format!("_visibility-{}", visibility));
+        }
+        for (flag, name) in [
+            (options.membership, "membership"),
+            (options.starred, "starred"),
+            (options.no_subgroups, "no-subgroups"),
+            (options.no_shared, "no-shared"),
+        ] {
+            if flag {
+                file_name.push_str(&format!("_{}", name));
+            }
+        }
+        let path: Option<PathBuf> = dirs::cache_dir().map(|cache_dir: PathBuf| {
+            cache_dir
+                .join("grgry")
+                .join(escape(&format!(
+                    "{}_{}",
+                    active_profile.baseaddress, active_profile.username
+                )))
+                .join(format!("{}.json", file_name))
+        });
+        let previous: BTreeMap<String, CachedPage> = path
+            .as_ref()
+            .and_then(|path: &PathBuf| fs::read_to_string(path).ok())
+            .and_then(|content: String| serde_json::from_str::<CacheFile>(&content).ok())
+            .map(|cache_file: CacheFile| cache_file.pages)
+            .unwrap_or_default();
+        Arc::new(ListingCache {
+            path,
+            ttl: active_profile.cachettl,
+            refresh: options.refresh,
+            previous,
+            current: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    // Returns the cached listing without any request if all pages are younger than the ttl
+    pub async fn fresh_repos(&self, provider: &str) -> Option<Vec<Box<dyn Repo>>> {
+        let now: u64 = now();
+        if self.refresh
+            || self.previous.is_empty()
+            || self
+                .previous
+                .values()
+                .any(|page: &CachedPage| now.saturating_sub(page.fetched_at) >= self.ttl)
+        {
+            return None;
+        }
+        self.cached_repos(provider).await
+    }
+
+    // Returns the cached listing regardless of its age, used if the provider is unreachable
+    pub async fn stale_repos(&self, provider: &str) -> Option<Vec<Box<dyn Repo>>> {
+        if self.refresh || self.previous.is_empty() {
+            return None;
+        }
+        self.cached_repos(provider).await
+    }
+
+    async fn cached_repos(&self, provider: &str) -> Option<Vec<Box<dyn Repo>>> {
+        let mut repos: Vec<Box<dyn Repo>> = Vec::new();
+        for (url, page) in &self.previous {
+            let page_repos: Vec<Box<dyn Repo>> =
+                parse_repos(url, to_response(page), provider).await.ok()?;
+            repos.extend(page_repos);
+        }
+        // The page count probe and the first page are the same request
+        repos.sort_by(|a, b| a.full_path().cmp(b.full_path()));
+        repos.dedup_by(|a, b| a.full_path() == b.full_path());
+        Some(repos)
+    }
+
+    // Like call_api, but answers 304 and unreachable servers from the cache
+    pub async fn call_api(
+        &self,
+        client: &Client,
+        endpoint: &str,
+        parameters: Option<&[(String, String)]>,
+        headers: Option<&[(String, String)]>,
+    ) -> Result<Response, ApiError> {
+        let key: String = cache_key(endpoint, parameters);
+        let cached: Option<CachedPage> = match self.refresh {
+            true => None,
+            false => self.previous.get(&key).cloned(),
+        };
+        let mut request: RequestBuilder = client.get(endpoint);
+        if let Some(params) = parameters {
+            request = request.query(params);
+        }
+        if let Some(header_pairs) = headers {
+            request = request.headers(to_header_map(endpoint, header_pairs)?);
+        }
+        if let Some(etag) = cached
+            .as_ref()
+            .and_then(|page: &CachedPage| page.etag.as_ref())
+        {
+            request = request.header("If-None-Match", etag);
+        }
+
+        // With a cached copy there is no point in waiting long for an unreachable server
+        let retries: u32 = if cached.is_some() { 1 } else { MAX_RETRIES };
+        let page: CachedPage = match send_with_retries(endpoint, request, retries).await {
+            Ok(response) if response.status() == StatusCode::NOT_MODIFIED => {
+                let mut page: CachedPage = cached.expect("Got 304 without a cached page");
+                page.fetched_at = now();
+                page
+            }
+            Ok(response) => to_cached_page(response).await,
+            Err(err) => match (err.status, cached) {
+                (None, Some(page)) => {
+                    eprintln!("{}", format!("{}, using the cached listing", err).yellow());
+                    page
+                }
+                _ => return Err(err),
+            },
+        };
+        let response: Response = to_response(&page);
+        self.current.lock().unwrap().insert(key, page);
+        Ok(response)
+    }
+
+    // Stores a page which was not fetched through call_api, the body must be in the REST format
+    pub fn store(&self, key: String, body: String) {
+        self.current.lock().unwrap().insert(
+            key,
+            CachedPage {
+                etag: None,
+                fetched_at: now(),
+                headers: Vec::new(),
+                body,
+            },
+        );
+    }
+
+    // Writes the pages of this run, pages which no longer exist are dropped
+    pub fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let cache_file: CacheFile = CacheFile {
+            pages: self.current.lock().unwrap().clone(),
+        };
+        let written: Result<(), String> = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| {
+                fs::write(
+                    path,
+                    serde_json::to_string(&cache_file).expect("Failed to serialize cache"),
+                )
+            })
+            .map_err(|err: std::io::Error| err.to_string());
+        if let Err(err) = written {
+            eprintln!(
+                "{}",
+                format!("Could not write cache {}: {}", path.display(), err).yellow()
+            );
+        }
+    }
+}
+
+async fn to_cached_page(response: Response) -> CachedPage {
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let etag: Option<String> = header("etag");
+    let headers: Vec<(String, String)> = CACHED_HEADERS
+        .iter()
+        .filter_map(|name: &&str| header(name).map(|value: String| (name.to_string(), value)))
+        .collect();
+    CachedPage {
+        etag,
+        fetched_at: now(),
+        headers,
+        body: response.text().await.unwrap_or_default(),
+    }
+}
+
+fn to_response(page: &CachedPage) -> Response {
+    let mut builder: http::response::Builder = http::Response::builder().status(200);
+    for (name, value) in &page.headers {
+        builder = builder.header(name, value);
+    }
+    Response::from(
+        builder
+            .body(page.body.clone())
+            .expect("Failed to rebuild cached response"),
+    )
+}
+
+// Sorted query parameters, so the same page is found regardless of the parameter order
+fn cache_key(endpoint: &str, parameters: Option<&[(String, String)]>) -> String {
+    let mut pairs: Vec<(String, String)> = parameters.map(<[_]>::to_vec).unwrap_or_default();
+    let mut url: Url = match Url::parse(endpoint) {
+        Ok(url) => url,
+        Err(_) => return endpoint.to_string(),
+    };
+    pairs.extend(
+        url.query_pairs()
+            .map(|(key, value)| (key.to_string(), value.to_string())),
+    );
+    pairs.sort();
+    url.set_query(None);
+    if !pairs.is_empty() {
+        url.query_pairs_mut().extend_pairs(pairs);
+    }
+    url.to_string()
+}
+
+fn escape(name: &str) -> String {
+    name.bytes()
+        .map(|byte: u8| match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now: Duration| now.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,155 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    env, fs,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    process::Command,
+};
+
+use reqwest::{Certificate, Client, ClientBuilder, Identity, Proxy};
+
+use crate::{
+    config::config::Profile,
+    utils::{
+        cmd::{run_cmd_r, GitConfig},
+        helper::{prntln, MessageType},
+    },
+};
+
+// Builds the client for the provider API with the network settings of the profile
+pub fn build_client(profile: &Profile) -> Client {
+    try_build_client(profile).unwrap_or_else(|err: String| exit_with(&err))
+}
+
+// Like build_client, but returns invalid network settings as error instead of exiting
+pub fn try_build_client(profile: &Profile) -> Result<Client, String> {
+    let mut builder: ClientBuilder = Client::builder();
+    builder = match profile.http1 {
+        true => builder.http1_only(),
+        false => builder.http2_prior_knowledge(),
+    };
+    if !profile.proxy.is_empty() {
+        builder = builder
+            .proxy(Proxy::all(&profile.proxy).map_err(|err| invalid_setting(&profile.proxy, err))?);
+    }
+    if !profile.cabundle.is_empty() {
+        // The bundle can contain the whole chain, each certificate is added as extra root
+        let bundle: Vec<u8> = read_file(&profile.cabundle)?;
+        for certificate in Certificate::from_pem_bundle(&bundle)
+            .map_err(|err| invalid_setting(&profile.cabundle, err))?
+        {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    if !profile.clientcert.is_empty() {
+        let mut pem: Vec<u8> = read_file(&profile.clientcert)?;
+        if !profile.clientkey.is_empty() {
+            pem.push(b'\n');
+            pem.extend(read_file(&profile.clientkey)?);
+        }
+        builder = builder.identity(
+            Identity::from_pem(&pem).map_err(|err| invalid_setting(&profile.clientcert, err))?,
+        );
+    }
+    builder
+        .build()
+        .map_err(|err: reqwest::Error| format!("Failed to build the HTTP client: {}", err))
+}
+
+// The same settings as -c arguments for the git commands
+pub fn git_http_config(profile: &Profile) -> GitConfig {
+    let mut config: Vec<String> = Vec::new();
+    let cabundle: String = match profile.cabundle.is_empty() {
+        true => String::new(),
+        false => git_ca_bundle(&profile.cabundle),
+    };
+    for (key, value) in [
+        ("http.proxy", &profile.proxy),
+        ("http.sslCAInfo", &cabundle),
+        ("http.sslCert", &profile.clientcert),
+        ("http.sslKey", &profile.clientkey),
+    ] {
+        if !value.is_empty() {
+            config.push(format!("{}={}", key, value));
+        }
+    }
+    if profile.http1 {
+        config.push("http.version=HTTP/1.1".to_string());
+    }
+    GitConfig::new(config)
+}
+
+// http.sslCAInfo replaces the CA store of git, so the extra bundle is appended to the one git
+// would use otherwise and the combined file is kept in the cache directory
+fn git_ca_bundle(cabundle: &str) -> String {
+    let Some(system_bundle) = system_ca_bundle().and_then(|path: PathBuf| fs::read(path).ok())
+    else {
+        return cabundle.to_string();
+    };
+    let mut content: Vec<u8> = system_bundle;
+    content.push(b'\n');
+    content.extend(read_file(cabundle).unwrap_or_else(|err: String| exit_with(&err)));
+    let mut hasher: DefaultHasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    let Some(path) = dirs::cache_dir().map(|cache_dir: PathBuf| {
+        cache_dir
+            .join("grgry")
+            .join(format!("ca-bundle-{:x}.pem", hasher.finish()))
+    }) else {
+        return cabundle.to_string();
+    };
+    if !path.exists() {
+        let written: bool = path
+            .parent()
+            .map(|dir| fs::create_dir_all(dir).is_ok())
+            .unwrap_or(false)
+            && fs::write(&path, &content).is_ok();
+        if !written {
+            return cabundle.to_string();
+        }
+    }
+    path.to_string_lossy().to_string()
+}
+
+// The CA bundle of git: GIT_SSL_CAINFO, a configured http.sslCAInfo, SSL_CERT_FILE or the
+// bundle of the distribution
+fn system_ca_bundle() -> Option<PathBuf> {
+    let configured: Option<String> = env::var("GIT_SSL_CAINFO")
+        .ok()
+        .or_else(|| {
+            run_cmd_r(
+                Command::new("git").args(["config", "--get", "http.sslCAInfo"]),
+                false,
+            )
+            .ok()
+        })
+        .or_else(|| env::var("SSL_CERT_FILE").ok())
+        .filter(|path: &String| !path.is_empty());
+    if let Some(path) = configured {
+        return Some(PathBuf::from(path));
+    }
+    [
+        "/etc/ssl/certs/ca-certificates.crt",
+        "/etc/pki/tls/certs/ca-bundle.crt",
+        "/etc/ssl/ca-bundle.pem",
+        "/etc/ssl/cert.pem",
+        "/usr/local/etc/openssl/cert.pem",
+        "/opt/homebrew/etc/openssl@3/cert.pem",
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .find(|path: &PathBuf| path.is_file())
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|err| invalid_setting(path, err))
+}
+
+fn invalid_setting<E: std::fmt::Display>(setting: &str, err: E) -> String {
+    format!("Profile setting {} is invalid: {}", setting, err)
+}
+
+fn exit_with(message: &str) -> ! {
+    prntln(message, MessageType::Error);
+    std::process::exit(1);
+}
@@ -1,14 +1,101 @@
-use std::{process, sync::Arc};
+use std::{
+    fmt, process,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use crate::{config::config::Profile, git_api::github::GithubRepo, git_api::gitlab::GitlabRepo};
+use crate::{
+    config::config::Profile, git_api::cache::ListingCache, git_api::github::GithubRepo,
+    git_api::gitlab::GitlabRepo,
+};
 use colored::Colorize;
 use reqwest::{
     header::{HeaderMap, HeaderName, HeaderValue},
-    Client, Response,
+    Client, RequestBuilder, Response, StatusCode,
 };
-use tokio::task;
+use tokio::{task, time::sleep};
+
+pub(crate) const MAX_RETRIES: u32 = 5;
+// Waiting longer than this for a rate limit reset is not worth it, better tell the user
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(300);
+
+#[derive(Debug)]
+pub struct ApiError {
+    pub endpoint: String,
+    pub status: Option<StatusCode>,
+    pub message: String,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.status {
+            Some(status) => write!(
+                f,
+                "Request to {} failed with HTTP {}: {}",
+                self.endpoint, status, self.message
+            ),
+            None => write!(f, "Request to {} failed: {}", self.endpoint, self.message),
+        }
+    }
+}
+
+impl ApiError {
+    async fn from_response(endpoint: &str, response: Response) -> Self {
+        let status: StatusCode = response.status();
+        let rate_limited: bool = is_rate_limited(&response);
+        let body: String = response.text().await.unwrap_or_default();
+        // Github and Gitlab both return the reason in a message field
+        let mut message: String = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|json: serde_json::Value| json["message"].as_str().map(str::to_string))
+            .unwrap_or(body.chars().take(200).collect());
+        if rate_limited {
+            message = format!("rate limit exceeded, try again later ({})", message);
+        }
+        ApiError {
+            endpoint: endpoint.to_string(),
+            status: Some(status),
+            message,
+        }
+    }
 
-use super::{github::Github, gitlab::Gitlab};
+    pub fn exit(&self) -> ! {
+        eprintln!("{}", self.to_string().red());
+        process::exit(1);
+    }
+}
+
+use super::{
+    github::Github,
+    gitlab::Gitlab,
+    link_header::{find_link, parse_link_header, Link},
+};
+
+// How the repositories of a collection are listed
+#[derive(Debug, Clone, Default)]
+pub struct ListOptions {
+    pub user: bool,
+    // Ignore the cached listing and fetch everything again
+    pub refresh: bool,
+    // Only for the repos of the token owner, e.g. owner, collaborator, organization_member
+    pub affiliation: Vec<String>,
+    // all, public or private, only for the repos of the token owner
+    pub visibility: Option<String>,
+    // All projects the token owner is a member of instead of a collection
+    pub membership: bool,
+    // All projects the token owner starred instead of a collection
+    pub starred: bool,
+    pub no_subgroups: bool,
+    // Skip projects shared with a group from another namespace
+    pub no_shared: bool,
+}
+
+impl ListOptions {
+    // Membership and starred list across all namespaces, so no collection is needed
+    pub fn without_collection(&self) -> bool {
+        self.membership || self.starred
+    }
+}
 
 // Define the trait in a common file (e.g., `git_provider.rs`)
 pub trait GitProvider {
@@ -17,23 +104,77 @@ pub trait GitProvider {
         client: Arc<Client>,
         endpoint: &str,
         headers: Option<Vec<(String, String)>>,
-    ) -> i32;
+        cache: Arc<ListingCache>,
+    ) -> Option<i32>; // None if the amount of pages is unknown and the next links must be followed
     fn get_repos(
         &self,
         client: Arc<Client>,
         pat: &Option<String>,
         collection_name: &str,
-        user: bool,
+        options: &ListOptions,
         active_profile: Profile,
     ) -> Vec<Box<dyn Repo>>;
+    // Fetches repositories by their full path including details like the parent of a fork
+    fn get_repos_by_path(
+        &self,
+        client: Arc<Client>,
+        pat: &Option<String>,
+        full_paths: &[String],
+        active_profile: Profile,
+    ) -> Vec<Result<Box<dyn Repo>, String>>;
+    // Lists the orgs / groups the token owner is a member of
+    fn get_own_collections(
+        &self,
+        _client: Arc<Client>,
+        _pat: &Option<String>,
+        _active_profile: Profile,
+    ) -> Result<Vec<String>, String> {
+        Err("Listing the own orgs is not supported by this provider".to_string())
+    }
+    // Returns the user the token belongs to, used to check if the token authenticates
+    fn get_token_owner(
+        &self,
+        _client: Arc<Client>,
+        _pat: &Option<String>,
+        _active_profile: Profile,
+    ) -> Result<String, String> {
+        Err("Checking the token is not supported by this provider".to_string())
+    }
+    fn sync_fork(
+        &self,
+        _client: Arc<Client>,
+        _pat: &Option<String>,
+        _repo: &dyn Repo,
+        _active_profile: Profile,
+    ) -> Result<String, String> {
+        Err("Syncing forks through the API is not supported by this provider".to_string())
+    }
 }
 
 pub trait Repo: Send + Sync {
     fn ssh_url(&self) -> &str;
     fn http_url(&self) -> &str;
     fn full_path(&self) -> &str;
+    fn default_branch(&self) -> Option<&str> {
+        None
+    }
+    fn fork(&self) -> bool {
+        false
+    }
+    fn archived(&self) -> bool {
+        false
+    }
+    fn topics(&self) -> &[String] {
+        &[]
+    }
+    // Only filled if the repo was fetched by get_repos_by_path
+    fn parent(&self) -> Option<&dyn Repo> {
+        None
+    }
 }
 
+type RepoTask = task::JoinHandle<Result<Vec<Box<dyn Repo>>, ApiError>>;
+
 pub async fn get_repos_paralell(
     client: Arc<Client>,
     pages: i32,
@@ -41,52 +182,159 @@ pub async fn get_repos_paralell(
     parameters: Option<Vec<(String, String)>>,
     headers: Option<Vec<(String, String)>>,
     provider: &str, // Enum to distinguish between Github and Gitlab
+    cache: Arc<ListingCache>,
 ) -> Vec<Box<dyn Repo>> {
-    let mut tasks: Vec<task::JoinHandle<Result<Vec<Box<dyn Repo>>, reqwest::Error>>> = Vec::new();
+    let mut tasks: Vec<RepoTask> = Vec::new();
     for page in 1..=pages {
         let endpoint_clone: String = endpoint.to_string();
         let mut parameters_clone: Option<Vec<(String, String)>> = parameters.clone();
         let headers_clone: Option<Vec<(String, String)>> = headers.clone();
         let provider: String = provider.to_string();
         let client_clone = Arc::clone(&client);
+        let cache_clone: Arc<ListingCache> = Arc::clone(&cache);
         if let Some(params) = &mut parameters_clone {
             params.push(("page".to_string(), page.to_string()));
         }
 
         tasks.push(task::spawn(async move {
-            let response: Response = call_api(
-                &client_clone,
-                &endpoint_clone,
-                parameters_clone.as_deref(),
-                headers_clone.as_deref(),
-            )
-            .await;
-            let repos: Vec<Box<dyn Repo>> = match provider.as_str() {
+            let response: Response = cache_clone
+                .call_api(
+                    &client_clone,
+                    &endpoint_clone,
+                    parameters_clone.as_deref(),
+                    headers_clone.as_deref(),
+                )
+                .await?;
+            parse_repos(&endpoint_clone, response, &provider).await
+        }));
+    }
+
+    let mut all_repos: Vec<Box<dyn Repo>> = Vec::new();
+    for task in tasks {
+        match task.await.expect("Fetching repositories panicked") {
+            Ok(repos) => all_repos.extend(repos),
+            Err(err) => err.exit(),
+        }
+    }
+
+    all_repos
+}
+
+// Follows the rel="next" links page by page, used if the amount of pages is unknown upfront
+pub async fn get_repos_sequential(
+    client: Arc<Client>,
+    endpoint: &str,
+    parameters: Option<Vec<(String, String)>>,
+    headers: Option<Vec<(String, String)>>,
+    provider: &str,
+    cache: Arc<ListingCache>,
+) -> Vec<Box<dyn Repo>> {
+    let mut all_repos: Vec<Box<dyn Repo>> = Vec::new();
+    let mut next: Option<String> = Some(endpoint.to_string());
+    let mut parameters: Option<Vec<(String, String)>> = parameters;
+    while let Some(url) = next {
+        let response: Response = cache
+            .call_api(&client, &url, parameters.as_deref(), headers.as_deref())
+            .await
+            .unwrap_or_else(|err: ApiError| err.exit());
+        next = next_link(&response).filter(|next_url: &String| *next_url != url);
+        // The next links already contain all query parameters
+        parameters = None;
+        let repos: Vec<Box<dyn Repo>> = parse_repos(&url, response, provider)
+            .await
+            .unwrap_or_else(|err: ApiError| err.exit());
+        all_repos.extend(repos);
+    }
+    all_repos
+}
+
+pub fn response_links(response: &Response) -> Vec<Link> {
+    response
+        .headers()
+        .get_all("link")
+        .iter()
+        .filter_map(|value: &HeaderValue| value.to_str().ok())
+        .flat_map(parse_link_header)
+        .collect()
+}
+
+fn next_link(response: &Response) -> Option<String> {
+    find_link(&response_links(response), "next").map(|link: &Link| link.url.clone())
+}
+
+pub async fn parse_repos(
+    endpoint: &str,
+    response: Response,
+    provider: &str,
+) -> Result<Vec<Box<dyn Repo>>, ApiError> {
+    let repos: Result<Vec<Box<dyn Repo>>, reqwest::Error> = match provider {
+        "gitlab" => response.json::<Vec<GitlabRepo>>().await.map(|repos| {
+            repos
+                .into_iter()
+                .map(|repo: GitlabRepo| Box::new(repo) as Box<dyn Repo>)
+                .collect()
+        }),
+        "github" => response.json::<Vec<GithubRepo>>().await.map(|repos| {
+            repos
+                .into_iter()
+                .map(|repo: GithubRepo| Box::new(repo) as Box<dyn Repo>)
+                .collect()
+        }),
+        _ => unreachable!(),
+    };
+    repos.map_err(|err: reqwest::Error| ApiError {
+        endpoint: endpoint.to_string(),
+        status: None,
+        message: format!("unexpected response ({})", err),
+    })
+}
+
+type SingleRepoTask = task::JoinHandle<Result<Box<dyn Repo>, String>>;
+
+pub async fn get_single_repos_paralell(
+    client: Arc<Client>,
+    endpoints: Vec<(String, String)>, // (full path, endpoint)
+    headers: Option<Vec<(String, String)>>,
+    provider: &str,
+) -> Vec<Result<Box<dyn Repo>, String>> {
+    let mut tasks: Vec<SingleRepoTask> = Vec::new();
+    for (full_path, endpoint) in endpoints {
+        let headers_clone: Option<Vec<(String, String)>> = headers.clone();
+        let provider: String = provider.to_string();
+        let client_clone = Arc::clone(&client);
+
+        tasks.push(task::spawn(async move {
+            let response: Response =
+                call_api(&client_clone, &endpoint, None, headers_clone.as_deref())
+                    .await
+                    .map_err(|err: ApiError| {
+                        format!("Repository {} could not be fetched: {}", full_path, err)
+                    })?;
+            let repo: Result<Box<dyn Repo>, reqwest::Error> = match provider.as_str() {
                 "gitlab" => response
-                    .json::<Vec<GitlabRepo>>()
-                    .await?
-                    .into_iter()
-                    .map(|repo: GitlabRepo| Box::new(repo) as Box<dyn Repo>)
-                    .collect(),
+                    .json::<GitlabRepo>()
+                    .await
+                    .map(|repo: GitlabRepo| Box::new(repo) as Box<dyn Repo>),
                 "github" => response
-                    .json::<Vec<GithubRepo>>()
-                    .await?
-                    .into_iter()
-                    .map(|repo: GithubRepo| Box::new(repo) as Box<dyn Repo>)
-                    .collect(),
+                    .json::<GithubRepo>()
+                    .await
+                    .map(|repo: GithubRepo| Box::new(repo) as Box<dyn Repo>),
                 _ => unreachable!(),
             };
-            Ok::<Vec<Box<dyn Repo>>, reqwest::Error>(repos)
+            repo.map_err(|err: reqwest::Error| {
+                format!("Repository {} could not be read: {}", full_path, err)
+            })
         }));
     }
 
-    let mut all_repos: Vec<Box<dyn Repo>> = Vec::new();
+    let mut repos: Vec<Result<Box<dyn Repo>, String>> = Vec::new();
     for task in tasks {
-        let repos: Vec<Box<dyn Repo>> = task.await.unwrap().unwrap();
-        all_repos.extend(repos);
+        // A panicking task only fails its own repository
+        repos.push(task.await.unwrap_or_else(|err: task::JoinError| {
+            Err(format!("Repository could not be fetched: {}", err))
+        }));
     }
-
-    all_repos
+    repos
 }
 
 pub async fn call_api(
@@ -94,42 +342,180 @@ pub async fn call_api(
     endpoint: &str,
     parameters: Option<&[(String, String)]>,
     headers: Option<&[(String, String)]>,
-) -> Response {
-    // let client: Client = Client::builder().build().expect("error while build client");
-    let mut request: reqwest::RequestBuilder = client.get(endpoint);
-    // .header("User-Agent", "grgry-cli"); //TODO: check if user agent makes sense
+) -> Result<Response, ApiError> {
+    let mut request: RequestBuilder = client.get(endpoint);
     if let Some(params) = parameters {
         request = request.query(params)
     }
 
     if let Some(header_pairs) = headers {
-        let mut header_map: HeaderMap = HeaderMap::new();
-        for (key, value) in header_pairs {
-            let header_name: HeaderName = HeaderName::from_bytes(key.as_bytes()).unwrap();
-            let header_value: HeaderValue = HeaderValue::from_str(value).unwrap();
-            header_map.insert(header_name, header_value);
-        }
-        request = request.headers(header_map);
+        request = request.headers(to_header_map(endpoint, header_pairs)?);
     }
 
-    let response: Response = match request.send().await {
-        Ok(resp) => resp,
-        Err(err) => {
-            eprintln!("{} {:?}", "Error sending request:".red(), err);
-            process::exit(1);
-        }
+    send_with_retries(endpoint, request, MAX_RETRIES).await
+}
+
+// Sends the request and retries on rate limits, 5xx and 429 with exponential backoff
+pub(crate) async fn send_with_retries(
+    endpoint: &str,
+    request: RequestBuilder,
+    max_retries: u32,
+) -> Result<Response, ApiError> {
+    let mut attempt: u32 = 0;
+    loop {
+        let response: Result<Response, reqwest::Error> = request
+            .try_clone()
+            .expect("Request body can not be retried")
+            .send()
+            .await;
+        let retry_in: Duration = match response {
+            Ok(response) if response.status().is_success() => return Ok(response),
+            Ok(response) if response.status() == StatusCode::NOT_MODIFIED => return Ok(response),
+            Ok(response) => match retry_delay(&response, attempt) {
+                Some(delay) if attempt < max_retries && delay <= MAX_RATE_LIMIT_WAIT => {
+                    eprintln!(
+                        "{}",
+                        format!(
+                            "Request to {} returned HTTP {}, retrying in {}s",
+                            endpoint,
+                            response.status(),
+                            delay.as_secs()
+                        )
+                        .yellow()
+                    );
+                    delay
+                }
+                _ => return Err(ApiError::from_response(endpoint, response).await),
+            },
+            Err(err) if attempt < max_retries && (err.is_timeout() || err.is_connect()) => {
+                backoff(attempt)
+            }
+            Err(err) => {
+                return Err(ApiError {
+                    endpoint: endpoint.to_string(),
+                    status: None,
+                    message: err.to_string(),
+                })
+            }
+        };
+        sleep(retry_in).await;
+        attempt += 1;
+    }
+}
+
+fn retry_delay(response: &Response, attempt: u32) -> Option<Duration> {
+    let status: StatusCode = response.status();
+    if let Some(seconds) = header_number(response, "retry-after") {
+        return Some(Duration::from_secs(seconds));
+    }
+    if is_rate_limited(response) {
+        // Github sends x-ratelimit-reset, Gitlab ratelimit-reset as unix timestamp
+        let now: u64 = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now: Duration| now.as_secs())
+            .unwrap_or(0);
+        return match header_number(response, "x-ratelimit-reset")
+            .or_else(|| header_number(response, "ratelimit-reset"))
+        {
+            Some(reset) => Some(Duration::from_secs(reset.saturating_sub(now) + 1)),
+            None => Some(backoff(attempt)),
+        };
+    }
+    if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+        return Some(backoff(attempt));
+    }
+    None
+}
+
+fn is_rate_limited(response: &Response) -> bool {
+    let status: StatusCode = response.status();
+    let remaining: Option<u64> = header_number(response, "x-ratelimit-remaining")
+        .or_else(|| header_number(response, "ratelimit-remaining"));
+    status == StatusCode::TOO_MANY_REQUESTS
+        || (status == StatusCode::FORBIDDEN && remaining == Some(0))
+}
+
+fn header_number(response: &Response, name: &str) -> Option<u64> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value: &HeaderValue| value.to_str().ok())
+        .and_then(|value: &str| value.trim().parse::<u64>().ok())
+}
+
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1 << attempt.min(6))
+}
+
+// The value is not part of the error, as it is usually the token
+pub(crate) fn to_header_map(
+    endpoint: &str,
+    header_pairs: &[(String, String)],
+) -> Result<HeaderMap, ApiError> {
+    let invalid_header = |key: &str| {
+        ApiError {
+        endpoint: endpoint.to_string(),
+        status: None,
+        message: format!(
+            "header {} is invalid, check the token of the profile for line breaks or other control characters",
+            key
+        ),
+    }
     };
+    let mut header_map: HeaderMap = HeaderMap::new();
+    for (key, value) in header_pairs {
+        let header_name: HeaderName =
+            HeaderName::from_bytes(key.as_bytes()).map_err(|_| invalid_header(key))?;
+        let header_value: HeaderValue =
+            HeaderValue::from_str(value).map_err(|_| invalid_header(key))?;
+        header_map.insert(header_name, header_value);
+    }
+    Ok(header_map)
+}
 
-    return response;
+pub async fn post_api(
+    client: &Client,
+    endpoint: &str,
+    body: &serde_json::Value,
+    headers: Option<&[(String, String)]>,
+) -> Result<Response, ApiError> {
+    let mut request: RequestBuilder = client.post(endpoint).json(body);
+
+    if let Some(header_pairs) = headers {
+        request = request.headers(to_header_map(endpoint, header_pairs)?);
+    }
+
+    send_with_retries(endpoint, request, MAX_RETRIES).await
 }
 
 pub fn get_provider(provider_type: &str) -> Box<dyn GitProvider> {
-    return match provider_type {
+    match provider_type {
         "gitlab" => Box::new(Gitlab),
         "github" => Box::new(Github),
         _ => {
-            println!("{} {} {}", "The provider type".red(), provider_type.red(), "is not supported or does not exist, for further https://github.com/Yingrjimsch/grgry/issues/new?assignees=&labels=question&projects=&template=FEATURE-REQUEST.yml");
+            println!("{} {} is not supported or does not exist, for further https://github.com/Yingrjimsch/grgry/issues/new?assignees=&labels=question&projects=&template=FEATURE-REQUEST.yml", "The provider type".red(), provider_type.red());
             unreachable!()
         }
-    };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_header_values_are_errors() {
+        let headers: Vec<(String, String)> =
+            vec![("Authorization".to_string(), "Bearer secret\n".to_string())];
+        let err: ApiError = to_header_map("https://example.com", &headers).unwrap_err();
+        assert!(!err.message.contains("secret"));
+    }
+
+    #[test]
+    fn valid_headers_are_kept() {
+        let headers: Vec<(String, String)> =
+            vec![("PRIVATE-TOKEN".to_string(), "glpat-secret".to_string())];
+        let header_map: HeaderMap = to_header_map("https://example.com", &headers).unwrap();
+        assert_eq!(header_map["private-token"], "glpat-secret");
+    }
 }
@@ -1,21 +1,45 @@
 use std::sync::Arc;
 
-use regex::Regex;
-use reqwest::{Client, Response};
-use serde::Deserialize;
+use colored::Colorize;
+use reqwest::{Client, Response, StatusCode};
+use serde::{Deserialize, Serialize};
 use tokio::task::block_in_place;
 
 use crate::{
     config::config::Profile,
-    git_api::git_providers::{call_api, get_repos_paralell, GitProvider, Repo},
+    git_api::cache::ListingCache,
+    git_api::git_providers::{
+        call_api, get_repos_paralell, get_repos_sequential, get_single_repos_paralell, post_api,
+        response_links, ApiError, GitProvider, ListOptions, Repo,
+    },
+    git_api::link_header::{find_link, Link},
+    utils::helper::{prntln, MessageType},
 };
 const PER_PAGE: i16 = 100;
 
-#[derive(Debug, Deserialize)]
+// Fetches the repos of an org / user in pages of 100 including the parent of forks
+const GRAPHQL_OWNER_QUERY: &str = "query($login: String!, $cursor: String) { repositoryOwner(login: $login) { repositories(first: 100, after: $cursor, ownerAffiliations: [OWNER]) { ...page } } }";
+// Same as the REST endpoint /user/repos, everything the token owner can access
+const GRAPHQL_VIEWER_QUERY: &str = "query($cursor: String, $affiliations: [RepositoryAffiliation], $privacy: RepositoryPrivacy) { viewer { repositories(first: 100, after: $cursor, ownerAffiliations: $affiliations, privacy: $privacy) { ...page } } }";
+const DEFAULT_AFFILIATION: [&str; 3] = ["owner", "collaborator", "organization_member"];
+const GRAPHQL_FRAGMENTS: &str = "fragment page on RepositoryConnection { pageInfo { hasNextPage endCursor } nodes { ...repo parent { ...repo } } } fragment repo on Repository { nameWithOwner sshUrl url isArchived isFork defaultBranchRef { name } repositoryTopics(first: 20) { nodes { topic { name } } } }";
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct GithubRepo {
     pub ssh_url: String,
     pub clone_url: String,
     pub full_name: String,
+    #[serde(default)]
+    pub default_branch: Option<String>,
+    #[serde(default)]
+    pub fork: bool,
+    #[serde(default)]
+    pub archived: bool,
+    #[serde(default)]
+    pub topics: Vec<String>,
+    // The listing endpoints don't contain the parent, only /repos/{owner}/{repo} does
+    #[serde(default)]
+    pub parent: Option<Box<GithubRepo>>,
 }
 
 impl Repo for GithubRepo {
@@ -30,6 +54,244 @@ impl Repo for GithubRepo {
     fn full_path(&self) -> &str {
         &self.full_name
     }
+
+    fn default_branch(&self) -> Option<&str> {
+        self.default_branch.as_deref()
+    }
+
+    fn fork(&self) -> bool {
+        self.fork
+    }
+
+    fn archived(&self) -> bool {
+        self.archived
+    }
+
+    fn topics(&self) -> &[String] {
+        &self.topics
+    }
+
+    fn parent(&self) -> Option<&dyn Repo> {
+        self.parent
+            .as_deref()
+            .map(|parent: &GithubRepo| parent as &dyn Repo)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphqlRepo {
+    name_with_owner: String,
+    ssh_url: String,
+    url: String,
+    #[serde(default)]
+    is_archived: bool,
+    #[serde(default)]
+    is_fork: bool,
+    default_branch_ref: Option<GraphqlBranch>,
+    repository_topics: Option<GraphqlTopics>,
+    parent: Option<Box<GraphqlRepo>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphqlBranch {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphqlTopics {
+    nodes: Vec<GraphqlTopic>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphqlTopic {
+    topic: GraphqlBranch,
+}
+
+impl From<GraphqlRepo> for GithubRepo {
+    fn from(repo: GraphqlRepo) -> Self {
+        GithubRepo {
+            ssh_url: repo.ssh_url,
+            clone_url: format!("{}.git", repo.url),
+            full_name: repo.name_with_owner,
+            default_branch: repo
+                .default_branch_ref
+                .map(|branch: GraphqlBranch| branch.name),
+            fork: repo.is_fork,
+            archived: repo.is_archived,
+            topics: repo
+                .repository_topics
+                .map(|topics: GraphqlTopics| {
+                    topics
+                        .nodes
+                        .into_iter()
+                        .map(|node: GraphqlTopic| node.topic.name)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            parent: repo
+                .parent
+                .map(|parent: Box<GraphqlRepo>| Box::new(GithubRepo::from(*parent))),
+        }
+    }
+}
+
+// Github Enterprise serves the REST API below /api/v3 and GraphQL below /api/graphql
+fn graphql_endpoint(baseaddress: &str) -> String {
+    let baseaddress: &str = baseaddress.trim_end_matches('/');
+    match baseaddress.strip_suffix("/api/v3") {
+        Some(host) => format!("{}/api/graphql", host),
+        None => format!("{}/graphql", baseaddress),
+    }
+}
+
+// Lists all repos with one query per 100 repos, login None lists the repos of the token owner
+async fn get_repos_graphql(
+    client: &Client,
+    endpoint: &str,
+    login: Option<&str>,
+    options: &ListOptions,
+    headers: Option<&[(String, String)]>,
+    cache: &ListingCache,
+) -> Result<Vec<Box<dyn Repo>>, ApiError> {
+    let query: String = format!(
+        "{} {}",
+        match login {
+            Some(_) => GRAPHQL_OWNER_QUERY,
+            None => GRAPHQL_VIEWER_QUERY,
+        },
+        GRAPHQL_FRAGMENTS
+    );
+    let to_error = |message: String| ApiError {
+        endpoint: endpoint.to_string(),
+        status: Some(StatusCode::OK),
+        message,
+    };
+    let mut repos: Vec<Box<dyn Repo>> = Vec::new();
+    let mut cursor: Option<String> = None;
+    loop {
+        let mut variables: serde_json::Value = serde_json::json!({ "cursor": cursor });
+        match login {
+            Some(login) => variables["login"] = serde_json::json!(login),
+            None => {
+                variables["affiliations"] = serde_json::json!(affiliation(options)
+                    .iter()
+                    .map(|affiliation: &String| affiliation.to_uppercase())
+                    .collect::<Vec<String>>());
+                if let Some(visibility) = options.visibility.as_deref().filter(|v| *v != "all") {
+                    variables["privacy"] = serde_json::json!(visibility.to_uppercase());
+                }
+            }
+        }
+        let body: serde_json::Value = serde_json::json!({ "query": query, "variables": variables });
+        let response: serde_json::Value = post_api(client, endpoint, &body, headers)
+            .await?
+            .json()
+            .await
+            .map_err(|err: reqwest::Error| to_error(format!("unexpected response ({})", err)))?;
+        // GraphQL reports errors like missing scopes with status 200
+        if let Some(errors) = response["errors"].as_array() {
+            return Err(to_error(
+                errors
+                    .iter()
+                    .filter_map(|error: &serde_json::Value| error["message"].as_str())
+                    .collect::<Vec<&str>>()
+                    .join(", "),
+            ));
+        }
+        let connection: &serde_json::Value = match login {
+            Some(_) => &response["data"]["repositoryOwner"]["repositories"],
+            None => &response["data"]["viewer"]["repositories"],
+        };
+        if connection.is_null() {
+            return Err(to_error(format!(
+                "{} was not found",
+                login.unwrap_or("viewer")
+            )));
+        }
+        let page: Vec<GithubRepo> =
+            serde_json::from_value::<Vec<GraphqlRepo>>(connection["nodes"].clone())
+                .map_err(|err: serde_json::Error| {
+                    to_error(format!("unexpected response ({})", err))
+                })?
+                .into_iter()
+                .map(GithubRepo::from)
+                .collect();
+        // Cached in the REST format, so a fresh cache does not care how it was fetched
+        cache.store(
+            format!(
+                "{}?login={}&cursor={}",
+                endpoint,
+                login.unwrap_or(""),
+                cursor.as_deref().unwrap_or("")
+            ),
+            serde_json::to_string(&page).expect("Failed to serialize repositories"),
+        );
+        repos.extend(
+            page.into_iter()
+                .map(|repo: GithubRepo| Box::new(repo) as Box<dyn Repo>),
+        );
+
+        cursor = connection["pageInfo"]["endCursor"]
+            .as_str()
+            .map(str::to_string);
+        if !connection["pageInfo"]["hasNextPage"]
+            .as_bool()
+            .unwrap_or(false)
+            || cursor.is_none()
+        {
+            return Ok(repos);
+        }
+    }
+}
+
+fn affiliation(options: &ListOptions) -> Vec<String> {
+    match options.affiliation.is_empty() {
+        true => DEFAULT_AFFILIATION
+            .iter()
+            .map(|a: &&str| a.to_string())
+            .collect(),
+        false => options.affiliation.clone(),
+    }
+}
+
+// Github tells with the type of /users/{name} if the name belongs to a user or an org
+async fn is_user(
+    client: &Client,
+    baseaddress: &str,
+    name: &str,
+    headers: Option<&[(String, String)]>,
+) -> Result<bool, ApiError> {
+    let endpoint: String = format!("{}/users/{}", baseaddress, name);
+    let owner: serde_json::Value = call_api(client, &endpoint, None, headers)
+        .await?
+        .json()
+        .await
+        .map_err(|err: reqwest::Error| ApiError {
+            endpoint: endpoint.clone(),
+            status: None,
+            message: format!("unexpected response ({})", err),
+        })?;
+    Ok(owner["type"].as_str() != Some("Organization"))
+}
+
+fn create_headers(pat: &Option<String>) -> Option<Vec<(String, String)>> {
+    pat.as_ref().map(|token: &String| {
+        let mut headers: Vec<(String, String)> =
+            vec![("User-Agent".to_string(), "grgry".to_string())];
+        // An empty token only lists public repos, github rejects an empty Authorization header
+        if !token.trim().is_empty() {
+            // Tokens from env or credential helpers usually come without the scheme
+            headers.push((
+                "Authorization".to_string(),
+                match token.contains(' ') {
+                    true => token.clone(),
+                    false => format!("token {}", token),
+                },
+            ));
+        }
+        headers
+    })
 }
 
 pub struct Github;
@@ -39,46 +301,139 @@ impl GitProvider for Github {
         client: Arc<Client>,
         pat: &Option<String>,
         collection_name: &str,
-        user: bool,
+        options: &ListOptions,
         active_profile: Profile,
     ) -> Vec<Box<dyn Repo>> {
         block_in_place(|| {
             let future = async {
-                let collection_searchstring: &str = match user {
-                    true => {
-                        if active_profile.username == collection_name && active_profile.token != ""
-                        {
-                            "user"
+                let cache: Arc<ListingCache> =
+                    ListingCache::load(&active_profile, collection_name, options);
+                if let Some(repos) = cache.fresh_repos(&active_profile.provider).await {
+                    return repos;
+                }
+                if options.without_collection() {
+                    prntln(
+                        "--membership and --starred are only supported on gitlab",
+                        MessageType::Error,
+                    );
+                    std::process::exit(1);
+                }
+                // The repos of the token owner include private and member repos
+                let own_user: bool =
+                    active_profile.username == collection_name && !active_profile.token.is_empty();
+                if !own_user && (!options.affiliation.is_empty() || options.visibility.is_some()) {
+                    prntln(
+                        "--affiliation and --visibility only work for the user of the profile token",
+                        MessageType::Error,
+                    );
+                    std::process::exit(1);
+                }
+                let headers: Option<Vec<(String, String)>> = create_headers(pat);
+                // GraphQL needs a token, without one or without access REST is used
+                if !active_profile.token.is_empty() {
+                    match get_repos_graphql(
+                        &client,
+                        &graphql_endpoint(&active_profile.baseaddress),
+                        if own_user {
+                            None
                         } else {
-                            &format!("users/{}", collection_name)
+                            Some(collection_name)
+                        },
+                        options,
+                        headers.as_deref(),
+                        &cache,
+                    )
+                    .await
+                    {
+                        Ok(repos) => {
+                            cache.save();
+                            return repos;
+                        }
+                        Err(err) if err.status.is_none() => {
+                            if let Some(repos) = cache.stale_repos(&active_profile.provider).await {
+                                eprintln!(
+                                    "{}",
+                                    format!("{}, using the cached listing", err).yellow()
+                                );
+                                return repos;
+                            }
+                            err.exit();
+                        }
+                        Err(err) => {
+                            eprintln!(
+                                "{}",
+                                format!("{}, falling back to the REST API", err).yellow()
+                            );
                         }
                     }
-                    false => &format!("orgs/{}", collection_name),
-                };
-                let endpoint: String = format!(
-                    "{}/{}/repos",
-                    &active_profile.baseaddress, collection_searchstring
-                ); //here the replace / --> %2F is not done because Github projects are top level on org or on user
-                let headers: Option<Vec<(String, String)>> = match pat {
-                    Some(token) => Some(vec![
-                        ("Authorization".to_string(), token.clone()),
-                        ("User-Agent".to_string(), "grgry".to_string()),
-                    ]),
-                    None => None,
+                }
+                let user: bool = own_user
+                    || options.user
+                    || is_user(
+                        &client,
+                        &active_profile.baseaddress,
+                        collection_name,
+                        headers.as_deref(),
+                    )
+                    .await
+                    .unwrap_or_else(|err: ApiError| err.exit());
+                let collection_searchstring: String = match (own_user, user) {
+                    // The filters are part of the endpoint, so the page count probe uses them too
+                    (true, _) => {
+                        let mut searchstring: String =
+                            format!("user?affiliation={}", affiliation(options).join(","));
+                        if let Some(visibility) = &options.visibility {
+                            searchstring.push_str(&format!("&visibility={}", visibility));
+                        }
+                        searchstring
+                    }
+                    (false, true) => format!("users/{}", collection_name),
+                    (false, false) => format!("orgs/{}", collection_name),
                 };
-                let pages: i32 =
-                    self.get_page_number(Arc::clone(&client), &endpoint, headers.clone());
+                let endpoint: String = match collection_searchstring.split_once('?') {
+                    Some((path, query)) => {
+                        format!("{}/{}/repos?{}", &active_profile.baseaddress, path, query)
+                    }
+                    None => format!(
+                        "{}/{}/repos",
+                        &active_profile.baseaddress, collection_searchstring
+                    ),
+                }; //here the replace / --> %2F is not done because Github projects are top level on org or on user
+                let pages: Option<i32> = self.get_page_number(
+                    Arc::clone(&client),
+                    &endpoint,
+                    headers.clone(),
+                    Arc::clone(&cache),
+                );
                 let parameters: Option<Vec<(String, String)>> =
                     Some(vec![("per_page".to_string(), PER_PAGE.to_string())]);
-                get_repos_paralell(
-                    client,
-                    pages,
-                    &endpoint,
-                    parameters,
-                    headers,
-                    &active_profile.provider,
-                )
-                .await
+                let repos: Vec<Box<dyn Repo>> = match pages {
+                    Some(pages) => {
+                        get_repos_paralell(
+                            client,
+                            pages,
+                            &endpoint,
+                            parameters,
+                            headers,
+                            &active_profile.provider,
+                            Arc::clone(&cache),
+                        )
+                        .await
+                    }
+                    None => {
+                        get_repos_sequential(
+                            client,
+                            &endpoint,
+                            parameters,
+                            headers,
+                            &active_profile.provider,
+                            Arc::clone(&cache),
+                        )
+                        .await
+                    }
+                };
+                cache.save();
+                repos
             };
 
             // Block on the async task, so it runs to completion and returns the result.
@@ -87,39 +442,170 @@ impl GitProvider for Github {
         })
     }
 
+    fn get_repos_by_path(
+        &self,
+        client: Arc<Client>,
+        pat: &Option<String>,
+        full_paths: &[String],
+        active_profile: Profile,
+    ) -> Vec<Result<Box<dyn Repo>, String>> {
+        block_in_place(|| {
+            let endpoints: Vec<(String, String)> = full_paths
+                .iter()
+                .map(|full_path: &String| {
+                    (
+                        full_path.clone(),
+                        format!("{}/repos/{}", active_profile.baseaddress, full_path),
+                    )
+                })
+                .collect();
+            tokio::runtime::Handle::current().block_on(get_single_repos_paralell(
+                client,
+                endpoints,
+                create_headers(pat),
+                &active_profile.provider,
+            ))
+        })
+    }
+
+    fn get_token_owner(
+        &self,
+        client: Arc<Client>,
+        pat: &Option<String>,
+        active_profile: Profile,
+    ) -> Result<String, String> {
+        block_in_place(|| {
+            let future = async {
+                let headers: Option<Vec<(String, String)>> = create_headers(pat);
+                let url: String = format!("{}/user", active_profile.baseaddress);
+                let user: serde_json::Value = call_api(&client, &url, None, headers.as_deref())
+                    .await
+                    .map_err(|err: ApiError| err.to_string())?
+                    .json()
+                    .await
+                    .map_err(|err: reqwest::Error| err.to_string())?;
+                user["login"]
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or(format!("{} returned no user", url))
+            };
+            tokio::runtime::Handle::current().block_on(future)
+        })
+    }
+
+    fn get_own_collections(
+        &self,
+        client: Arc<Client>,
+        pat: &Option<String>,
+        active_profile: Profile,
+    ) -> Result<Vec<String>, String> {
+        if active_profile.token.is_empty() {
+            return Err("Listing the own orgs needs a token in the profile".to_string());
+        }
+        block_in_place(|| {
+            let future = async {
+                let headers: Option<Vec<(String, String)>> = create_headers(pat);
+                let mut orgs: Vec<String> = Vec::new();
+                let mut next: Option<String> = Some(format!(
+                    "{}/user/orgs?per_page={}",
+                    active_profile.baseaddress, PER_PAGE
+                ));
+                while let Some(url) = next {
+                    let response: Response = call_api(&client, &url, None, headers.as_deref())
+                        .await
+                        .map_err(|err: ApiError| err.to_string())?;
+                    next = find_link(&response_links(&response), "next")
+                        .map(|link: &Link| link.url.clone());
+                    let page: Vec<serde_json::Value> = response
+                        .json()
+                        .await
+                        .map_err(|err: reqwest::Error| err.to_string())?;
+                    orgs.extend(page.iter().filter_map(|org: &serde_json::Value| {
+                        org["login"].as_str().map(str::to_string)
+                    }));
+                }
+                Ok(orgs)
+            };
+            tokio::runtime::Handle::current().block_on(future)
+        })
+    }
+
+    fn sync_fork(
+        &self,
+        client: Arc<Client>,
+        pat: &Option<String>,
+        repo: &dyn Repo,
+        active_profile: Profile,
+    ) -> Result<String, String> {
+        let branch: &str = repo.default_branch().ok_or(format!(
+            "Repository {} has no default branch",
+            repo.full_path()
+        ))?;
+        block_in_place(|| {
+            let future = async {
+                let endpoint: String = format!(
+                    "{}/repos/{}/merge-upstream",
+                    active_profile.baseaddress,
+                    repo.full_path()
+                );
+                let headers: Option<Vec<(String, String)>> = create_headers(pat);
+                match post_api(
+                    &client,
+                    &endpoint,
+                    &serde_json::json!({ "branch": branch }),
+                    headers.as_deref(),
+                )
+                .await
+                {
+                    Ok(response) => {
+                        let body: serde_json::Value = response.json().await.unwrap_or_default();
+                        Ok(format!(
+                            "Fork {} synced on branch {}: {}",
+                            repo.full_path(),
+                            branch,
+                            body["message"].as_str().unwrap_or("")
+                        ))
+                    }
+                    Err(err) => Err(format!(
+                        "Fork {} could not be synced: {}",
+                        repo.full_path(),
+                        err
+                    )),
+                }
+            };
+            tokio::runtime::Handle::current().block_on(future)
+        })
+    }
+
     fn get_page_number(
         &self,
         client: Arc<Client>,
         endpoint: &str,
         headers: Option<Vec<(String, String)>>,
-    ) -> i32 {
+        cache: Arc<ListingCache>,
+    ) -> Option<i32> {
         block_in_place(|| {
             let future = async {
                 let parameters: Option<Vec<(String, String)>> = Some(vec![
                     ("page".to_string(), "1".to_string()),
                     ("per_page".to_string(), PER_PAGE.to_string()),
                 ]);
-                let resp_total_repos: Response =
-                    call_api(&client, endpoint, parameters.as_deref(), headers.as_deref()).await;
-                let pages: i32 = match resp_total_repos.headers().get("link") {
-                    Some(page) => {
-                        let re: Regex = Regex::new(r"page=(\d+)").unwrap();
-                        re.captures_at(page.to_str().ok().unwrap(), 3)
-                            .unwrap()
-                            .get(1)
-                            .unwrap()
-                            .as_str()
-                            .parse::<i32>()
-                            .ok()
-                            .unwrap()
-                    }
-                    None => 1,
-                };
-                return pages;
+                let resp_total_repos: Response = cache
+                    .call_api(&client, endpoint, parameters.as_deref(), headers.as_deref())
+                    .await
+                    .unwrap_or_else(|err: ApiError| err.exit());
+                let links: Vec<Link> = response_links(&resp_total_repos);
+                match (find_link(&links, "last"), find_link(&links, "next")) {
+                    (Some(last), _) => last
+                        .query_param("page")
+                        .and_then(|page: String| page.parse::<i32>().ok()),
+                    (None, Some(_)) => None,
+                    (None, None) => Some(1),
+                }
             };
             // Block on the async task, so it runs to completion and returns the result.
-            let repos: i32 = tokio::runtime::Handle::current().block_on(future);
-            repos
+            let pages: Option<i32> = tokio::runtime::Handle::current().block_on(future);
+            pages
         })
     }
 }
@@ -2,10 +2,15 @@ use std::sync::Arc;
 
 use crate::{
     config::config::Profile,
-    git_api::git_providers::{call_api, get_repos_paralell, GitProvider, Repo},
+    git_api::cache::ListingCache,
+    git_api::git_providers::{
+        call_api, get_repos_paralell, get_repos_sequential, get_single_repos_paralell,
+        response_links, ApiError, GitProvider, ListOptions, Repo,
+    },
+    git_api::link_header::{find_link, Link},
 };
 
-use reqwest::{Client, Response};
+use reqwest::{Client, Response, Url};
 use serde::Deserialize;
 use tokio::task::block_in_place;
 const PER_PAGE: i16 = 100;
@@ -15,6 +20,10 @@ pub(crate) struct GitlabRepo {
     pub ssh_url_to_repo: String,
     pub http_url_to_repo: String,
     pub path_with_namespace: String,
+    #[serde(default)]
+    pub default_branch: Option<String>,
+    #[serde(default)]
+    pub topics: Vec<String>,
 }
 
 impl Repo for GitlabRepo {
@@ -29,6 +38,40 @@ impl Repo for GitlabRepo {
     fn full_path(&self) -> &str {
         &self.path_with_namespace
     }
+
+    fn default_branch(&self) -> Option<&str> {
+        self.default_branch.as_deref()
+    }
+
+    fn topics(&self) -> &[String] {
+        &self.topics
+    }
+}
+
+fn create_headers(pat: &Option<String>) -> Option<Vec<(String, String)>> {
+    pat.as_ref().map(|token: &String| {
+        let mut headers: Vec<(String, String)> =
+            vec![("User-Agent".to_string(), "grgry".to_string())];
+        // An empty token only lists public projects
+        if !token.trim().is_empty() {
+            headers.push(("Private-Token".to_string(), token.clone()));
+        }
+        headers
+    })
+}
+
+// Keyset pagination is only supported by /projects, the group and user listings use offsets
+fn sequential_parameters(options: &ListOptions) -> Vec<(String, String)> {
+    let mut parameters: Vec<(String, String)> =
+        vec![("per_page".to_string(), PER_PAGE.to_string())];
+    if options.membership || options.starred {
+        parameters.extend([
+            ("pagination".to_string(), "keyset".to_string()),
+            ("order_by".to_string(), "id".to_string()),
+            ("sort".to_string(), "asc".to_string()),
+        ]);
+    }
+    parameters
 }
 
 pub struct Gitlab;
@@ -38,44 +81,91 @@ impl GitProvider for Gitlab {
         client: Arc<Client>,
         pat: &Option<String>,
         collection_name: &str,
-        user: bool,
+        options: &ListOptions,
         active_profile: Profile,
     ) -> Vec<Box<dyn Repo>> {
         block_in_place(|| {
             let future = async {
-                let collection_type: &str = match user {
-                    true => "users",
-                    false => "groups",
+                let cache: Arc<ListingCache> =
+                    ListingCache::load(&active_profile, collection_name, options);
+                if let Some(repos) = cache.fresh_repos(&active_profile.provider).await {
+                    return repos;
+                }
+                // The filters are part of the endpoint, so the page count probe uses them too
+                let mut query: Vec<(&str, &str)> = vec![("simple", "true")];
+                let endpoint: String = if options.membership || options.starred {
+                    if options.membership {
+                        query.push(("membership", "true"));
+                    }
+                    if options.starred {
+                        query.push(("starred", "true"));
+                    }
+                    format!("{}/api/v4/projects", active_profile.baseaddress)
+                } else if options.user {
+                    format!(
+                        "{}/api/v4/users/{}/projects",
+                        active_profile.baseaddress,
+                        collection_name.replace("/", "%2F")
+                    )
+                } else {
+                    query.push((
+                        "include_subgroups",
+                        if options.no_subgroups {
+                            "false"
+                        } else {
+                            "true"
+                        },
+                    ));
+                    if options.no_shared {
+                        query.push(("with_shared", "false"));
+                    }
+                    format!(
+                        "{}/api/v4/groups/{}/projects",
+                        active_profile.baseaddress,
+                        collection_name.replace("/", "%2F")
+                    )
                 };
-                let endpoint: String = format!(
-                    "{}/api/v4/{}/{}/projects",
-                    active_profile.baseaddress,
-                    collection_type,
-                    collection_name.replace("/", "%2F")
+                let endpoint: String = Url::parse_with_params(&endpoint, &query)
+                    .map(|url: Url| url.to_string())
+                    .unwrap_or(endpoint);
+                let headers: Option<Vec<(String, String)>> = create_headers(pat);
+                let pages: Option<i32> = self.get_page_number(
+                    Arc::clone(&client),
+                    &endpoint,
+                    headers.clone(),
+                    Arc::clone(&cache),
                 );
-                let headers: Option<Vec<(String, String)>> = match pat {
-                    Some(token) => Some(vec![
-                        ("Private-Token".to_string(), token.clone()),
-                        ("User-Agent".to_string(), "grgry".to_string()),
-                    ]),
-                    None => None,
+                let parameters: Option<Vec<(String, String)>> =
+                    Some(vec![("per_page".to_string(), PER_PAGE.to_string())]);
+                let repos: Vec<Box<dyn Repo>> = match pages {
+                    Some(pages) => {
+                        get_repos_paralell(
+                            client,
+                            pages,
+                            &endpoint,
+                            parameters,
+                            headers,
+                            &active_profile.provider,
+                            Arc::clone(&cache),
+                        )
+                        .await
+                    }
+                    // Gitlab omits x-total-pages for more than 10000 projects, the pages are
+                    // then followed by their rel="next" links
+                    None => {
+                        get_repos_sequential(
+                            client,
+                            &endpoint,
+                            Some(sequential_parameters(options)),
+                            headers,
+                            &active_profile.provider,
+                            Arc::clone(&cache),
+                        )
+                        .await
+                    }
                 };
-                let pages: i32 =
-                    self.get_page_number(Arc::clone(&client), &endpoint, headers.clone());
-                let parameters: Option<Vec<(String, String)>> = Some(vec![
-                    ("include_subgroups".to_string(), "true".to_string()),
-                    ("simple".to_string(), "true".to_string()),
-                    ("per_page".to_string(), PER_PAGE.to_string()),
-                ]);
-                get_repos_paralell(
-                    client,
-                    pages,
-                    &endpoint,
-                    parameters,
-                    headers,
-                    &active_profile.provider,
-                )
-                .await
+                cache.save();
+                repos
             };
 
             // Block on the async task, so it runs to completion and returns the result.
@@ -84,32 +174,241 @@ impl GitProvider for Gitlab {
         })
     }
 
+    fn get_repos_by_path(
+        &self,
+        client: Arc<Client>,
+        pat: &Option<String>,
+        full_paths: &[String],
+        active_profile: Profile,
+    ) -> Vec<Result<Box<dyn Repo>, String>> {
+        block_in_place(|| {
+            let endpoints: Vec<(String, String)> = full_paths
+                .iter()
+                .map(|full_path: &String| {
+                    (
+                        full_path.clone(),
+                        format!(
+                            "{}/api/v4/projects/{}",
+                            active_profile.baseaddress,
+                            full_path.replace('/', "%2F")
+                        ),
+                    )
+                })
+                .collect();
+            tokio::runtime::Handle::current().block_on(get_single_repos_paralell(
+                client,
+                endpoints,
+                create_headers(pat),
+                &active_profile.provider,
+            ))
+        })
+    }
+
+    fn get_token_owner(
+        &self,
+        client: Arc<Client>,
+        pat: &Option<String>,
+        active_profile: Profile,
+    ) -> Result<String, String> {
+        block_in_place(|| {
+            let future = async {
+                let headers: Option<Vec<(String, String)>> = create_headers(pat);
+                let url: String = format!("{}/api/v4/user", active_profile.baseaddress);
+                let user: serde_json::Value = call_api(&client, &url, None, headers.as_deref())
+                    .await
+                    .map_err(|err: ApiError| err.to_string())?
+                    .json()
+                    .await
+                    .map_err(|err: reqwest::Error| err.to_string())?;
+                user["username"]
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or(format!("{} returned no user", url))
+            };
+            tokio::runtime::Handle::current().block_on(future)
+        })
+    }
+
+    // The top-level groups the token owner is a member of, subgroups are listed by them
+    fn get_own_collections(
+        &self,
+        client: Arc<Client>,
+        pat: &Option<String>,
+        active_profile: Profile,
+    ) -> Result<Vec<String>, String> {
+        if active_profile.token.is_empty() {
+            return Err("Listing the own groups needs a token in the profile".to_string());
+        }
+        block_in_place(|| {
+            let future = async {
+                let headers: Option<Vec<(String, String)>> = create_headers(pat);
+                let mut groups: Vec<String> = Vec::new();
+                let mut next: Option<String> = Some(format!(
+                    "{}/api/v4/groups?top_level_only=true&min_access_level=10&per_page={}",
+                    active_profile.baseaddress, PER_PAGE
+                ));
+                while let Some(url) = next {
+                    let response: Response = call_api(&client, &url, None, headers.as_deref())
+                        .await
+                        .map_err(|err: ApiError| err.to_string())?;
+                    next = find_link(&response_links(&response), "next")
+                        .map(|link: &Link| link.url.clone());
+                    let page: Vec<serde_json::Value> = response
+                        .json()
+                        .await
+                        .map_err(|err: reqwest::Error| err.to_string())?;
+                    groups.extend(page.iter().filter_map(|group: &serde_json::Value| {
+                        group["full_path"].as_str().map(str::to_string)
+                    }));
+                }
+                Ok(groups)
+            };
+            tokio::runtime::Handle::current().block_on(future)
+        })
+    }
+
     fn get_page_number(
         &self,
         client: Arc<Client>,
         endpoint: &str,
         headers: Option<Vec<(String, String)>>,
-    ) -> i32 {
+        cache: Arc<ListingCache>,
+    ) -> Option<i32> {
         block_in_place(|| {
             let future = async {
                 let parameters: Option<Vec<(String, String)>> = Some(vec![
-                    ("include_subgroups".to_string(), "true".to_string()),
-                    ("simple".to_string(), "true".to_string()),
                     ("page".to_string(), "1".to_string()),
                     ("per_page".to_string(), PER_PAGE.to_string()),
                 ]);
-                let resp_total_repos: Response =
-                    call_api(&client, endpoint, parameters.as_deref(), headers.as_deref()).await;
-                return resp_total_repos
+                let resp_total_repos: Response = cache
+                    .call_api(&client, endpoint, parameters.as_deref(), headers.as_deref())
+                    .await
+                    .unwrap_or_else(|err: ApiError| err.exit());
+                resp_total_repos
                     .headers()
                     .get("x-total-pages")
                     .and_then(|hv: &reqwest::header::HeaderValue| hv.to_str().ok())
                     .and_then(|s: &str| s.parse::<i32>().ok())
-                    .unwrap();
             };
             // Block on the async task, so it runs to completion and returns the result.
-            let repos: i32 = tokio::runtime::Handle::current().block_on(future);
-            repos
+            let pages: Option<i32> = tokio::runtime::Handle::current().block_on(future);
+            pages
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git_api::git_providers::get_repos_sequential;
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        thread,
+    };
+
+    fn project(full_path: &str) -> String {
+        format!(
+            r#"{{"ssh_url_to_repo":"git@example.com:{0}.git","http_url_to_repo":"https://example.com/{0}.git","path_with_namespace":"{0}"}}"#,
+            full_path
+        )
+    }
+
+    // Answers one request per page and returns the request lines it got
+    fn serve(listener: TcpListener, pages: Vec<(String, Option<String>)>) -> Vec<String> {
+        let mut requests: Vec<String> = Vec::new();
+        for (body, link) in pages {
+            let (mut stream, _) = listener.accept().unwrap();
+            requests.push(read_request_line(&mut stream));
+            let link: String = link
+                .map(|url: String| format!("Link: <{}>; rel=\"next\"\r\n", url))
+                .unwrap_or_default();
+            let response: String = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                link,
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+        requests
+    }
+
+    fn read_request_line(stream: &mut TcpStream) -> String {
+        let mut request: Vec<u8> = Vec::new();
+        let mut buffer: [u8; 1024] = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let read: usize = stream.read(&mut buffer).unwrap();
+            if read == 0 {
+                break;
+            }
+            request.extend_from_slice(&buffer[..read]);
+        }
+        String::from_utf8_lossy(&request)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    #[test]
+    fn uses_keyset_only_for_projects() {
+        let membership: ListOptions = ListOptions {
+            membership: true,
+            ..Default::default()
+        };
+        let keyset: Vec<(String, String)> = sequential_parameters(&membership);
+        assert!(keyset.contains(&("pagination".to_string(), "keyset".to_string())));
+        assert!(keyset.contains(&("order_by".to_string(), "id".to_string())));
+
+        let group: Vec<(String, String)> = sequential_parameters(&ListOptions::default());
+        assert_eq!(group, vec![("per_page".to_string(), PER_PAGE.to_string())]);
+    }
+
+    #[tokio::test]
+    async fn follows_next_links_with_keyset_pagination() {
+        let listener: TcpListener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base: String = format!("http://{}", listener.local_addr().unwrap());
+        let next: String = format!(
+            "{}/api/v4/projects?membership=true&pagination=keyset&order_by=id&sort=asc&per_page=100&id_after=2",
+            base
+        );
+        let pages: Vec<(String, Option<String>)> = vec![
+            (
+                format!("[{},{}]", project("team/a"), project("team/b")),
+                Some(next),
+            ),
+            (format!("[{}]", project("team/c")), None),
+        ];
+        let server: thread::JoinHandle<Vec<String>> = thread::spawn(move || serve(listener, pages));
+
+        let options: ListOptions = ListOptions {
+            membership: true,
+            refresh: true,
+            ..Default::default()
+        };
+        let profile: Profile = Profile {
+            baseaddress: base.clone(),
+            provider: "gitlab".to_string(),
+            ..Default::default()
+        };
+        let client: Arc<Client> = Arc::new(Client::builder().no_proxy().build().unwrap());
+        let repos: Vec<Box<dyn Repo>> = get_repos_sequential(
+            client,
+            &format!("{}/api/v4/projects?membership=true", base),
+            Some(sequential_parameters(&options)),
+            None,
+            "gitlab",
+            ListingCache::load(&profile, "", &options),
+        )
+        .await;
+
+        let full_paths: Vec<&str> = repos.iter().map(|repo| repo.full_path()).collect();
+        assert_eq!(full_paths, vec!["team/a", "team/b", "team/c"]);
+        let requests: Vec<String> = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains("pagination=keyset"));
+        assert!(requests[0].contains("order_by=id"));
+        assert!(requests[1].contains("id_after=2"));
+    }
+}
@@ -0,0 +1,240 @@
+use reqwest::Url;
+
+// A single link of a Link header (RFC 8288) like <https://api.github.com/orgs/x/repos?page=2>; rel="next"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub url: String,
+    pub rels: Vec<String>,
+    pub params: Vec<(String, String)>,
+}
+
+impl Link {
+    pub fn has_rel(&self, rel: &str) -> bool {
+        self.rels
+            .iter()
+            .any(|r: &String| r.eq_ignore_ascii_case(rel))
+    }
+
+    pub fn query_param(&self, name: &str) -> Option<String> {
+        Url::parse(&self.url).ok().and_then(|url: Url| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+}
+
+pub fn find_link<'a>(links: &'a [Link], rel: &str) -> Option<&'a Link> {
+    links.iter().find(|link: &&Link| link.has_rel(rel))
+}
+
+// Parses a Link header value, malformed links are skipped instead of failing the whole header
+pub fn parse_link_header(header: &str) -> Vec<Link> {
+    let chars: Vec<char> = header.chars().collect();
+    let mut links: Vec<Link> = Vec::new();
+    let mut pos: usize = 0;
+
+    while pos < chars.len() {
+        skip_while(&chars, &mut pos, |c: char| c.is_whitespace() || c == ',');
+        if pos >= chars.len() {
+            break;
+        }
+        if chars[pos] != '<' {
+            skip_to_next_link(&chars, &mut pos);
+            continue;
+        }
+        pos += 1;
+        let url_start: usize = pos;
+        skip_while(&chars, &mut pos, |c: char| c != '>');
+        if pos >= chars.len() {
+            break;
+        }
+        let url: String = chars[url_start..pos].iter().collect::<String>();
+        pos += 1;
+
+        let mut params: Vec<(String, String)> = Vec::new();
+        loop {
+            skip_while(&chars, &mut pos, char::is_whitespace);
+            if pos >= chars.len() || chars[pos] != ';' {
+                break;
+            }
+            pos += 1;
+            skip_while(&chars, &mut pos, char::is_whitespace);
+            let name_start: usize = pos;
+            skip_while(&chars, &mut pos, |c: char| {
+                c != '=' && c != ';' && c != ',' && !c.is_whitespace()
+            });
+            let name: String = chars[name_start..pos]
+                .iter()
+                .collect::<String>()
+                .to_lowercase();
+            skip_while(&chars, &mut pos, char::is_whitespace);
+            let value: String = if pos < chars.len() && chars[pos] == '=' {
+                pos += 1;
+                skip_while(&chars, &mut pos, char::is_whitespace);
+                parse_value(&chars, &mut pos)
+            } else {
+                String::new()
+            };
+            if !name.is_empty() {
+                params.push((name, value));
+            }
+        }
+
+        let rels: Vec<String> = params
+            .iter()
+            .filter(|(name, _)| name == "rel")
+            .flat_map(|(_, value)| value.split_whitespace())
+            .map(str::to_lowercase)
+            .collect();
+        links.push(Link {
+            url: url.trim().to_string(),
+            rels,
+            params,
+        });
+        // Anything else up to the next comma is not part of a valid link
+        skip_to_next_link(&chars, &mut pos);
+    }
+    links
+}
+
+fn parse_value(chars: &[char], pos: &mut usize) -> String {
+    let mut value: String = String::new();
+    if *pos < chars.len() && chars[*pos] == '"' {
+        *pos += 1;
+        while *pos < chars.len() && chars[*pos] != '"' {
+            if chars[*pos] == '\\' && *pos + 1 < chars.len() {
+                *pos += 1;
+            }
+            value.push(chars[*pos]);
+            *pos += 1;
+        }
+        *pos += 1;
+    } else {
+        let start: usize = *pos;
+        skip_while(chars, pos, |c: char| c != ';' && c != ',');
+        value = chars[start..*pos]
+            .iter()
+            .collect::<String>()
+            .trim()
+            .to_string();
+    }
+    value
+}
+
+fn skip_while<F: Fn(char) -> bool>(chars: &[char], pos: &mut usize, predicate: F) {
+    while *pos < chars.len() && predicate(chars[*pos]) {
+        *pos += 1;
+    }
+}
+
+fn skip_to_next_link(chars: &[char], pos: &mut usize) {
+    let mut quoted: bool = false;
+    while *pos < chars.len() {
+        match chars[*pos] {
+            '"' => quoted = !quoted,
+            ',' if !quoted => return,
+            _ => {}
+        }
+        *pos += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_github_pagination() {
+        let header: &str = r#"<https://api.github.com/organizations/9919/repos?per_page=100&page=2>; rel="next", <https://api.github.com/organizations/9919/repos?per_page=100&page=7>; rel="last""#;
+        let links: Vec<Link> = parse_link_header(header);
+        assert_eq!(links.len(), 2);
+        assert_eq!(
+            find_link(&links, "next").unwrap().query_param("page"),
+            Some("2".to_string())
+        );
+        assert_eq!(
+            find_link(&links, "last").unwrap().query_param("page"),
+            Some("7".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_github_enterprise_with_page_before_per_page() {
+        let header: &str = r#"<https://ghe.example.com/api/v3/orgs/team/repos?page=2&per_page=100>; rel="next", <https://ghe.example.com/api/v3/orgs/team/repos?page=12&per_page=100>; rel="last""#;
+        let links: Vec<Link> = parse_link_header(header);
+        assert_eq!(
+            find_link(&links, "last").unwrap().query_param("page"),
+            Some("12".to_string())
+        );
+        assert_eq!(
+            find_link(&links, "last").unwrap().query_param("per_page"),
+            Some("100".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_middle_page_with_all_relations() {
+        let header: &str = r#"<https://api.github.com/user/repos?page=3&per_page=100>; rel="next", <https://api.github.com/user/repos?page=50&per_page=100>; rel="last", <https://api.github.com/user/repos?page=1&per_page=100>; rel="first", <https://api.github.com/user/repos?page=1&per_page=100>; rel="prev""#;
+        let links: Vec<Link> = parse_link_header(header);
+        assert_eq!(links.len(), 4);
+        assert!(find_link(&links, "first").is_some());
+        assert!(find_link(&links, "prev").is_some());
+        assert_eq!(
+            find_link(&links, "last").unwrap().query_param("page"),
+            Some("50".to_string())
+        );
+    }
+
+    #[test]
+    fn parses_gitea_without_quotes_and_spaces() {
+        let header: &str = "<https://gitea.example.com/api/v1/orgs/team/repos?limit=50&page=2>;rel=next,<https://gitea.example.com/api/v1/orgs/team/repos?limit=50&page=4>;rel=last";
+        let links: Vec<Link> = parse_link_header(header);
+        assert_eq!(links.len(), 2);
+        assert_eq!(
+            find_link(&links, "next").unwrap().query_param("page"),
+            Some("2".to_string())
+        );
+        assert_eq!(
+            find_link(&links, "last").unwrap().query_param("page"),
+            Some("4".to_string())
+        );
+    }
+
+    #[test]
+    fn missing_last_falls_back_to_next() {
+        let header: &str = r#"<https://api.github.com/search/code?q=x&page=2>; rel="next""#;
+        let links: Vec<Link> = parse_link_header(header);
+        assert!(find_link(&links, "last").is_none());
+        assert!(find_link(&links, "next").is_some());
+    }
+
+    #[test]
+    fn handles_multiple_relations_and_quoted_commas() {
+        let header: &str = r#"<https://example.com/a?page=3>; title="a, b; c"; rel="next LAST", <https://example.com/a?page=1>; rel=first"#;
+        let links: Vec<Link> = parse_link_header(header);
+        assert_eq!(links.len(), 2);
+        assert!(links[0].has_rel("next"));
+        assert!(links[0].has_rel("last"));
+        assert_eq!(
+            links[0].params[0],
+            ("title".to_string(), "a, b; c".to_string())
+        );
+        assert!(links[1].has_rel("first"));
+    }
+
+    #[test]
+    fn skips_malformed_links() {
+        let header: &str =
+            r#"garbage; rel="next", <https://example.com/a?page=5>; rel="last", <unterminated"#;
+        let links: Vec<Link> = parse_link_header(header);
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].query_param("page"), Some("5".to_string()));
+    }
+
+    #[test]
+    fn empty_header_has_no_links() {
+        assert!(parse_link_header("").is_empty());
+        assert!(parse_link_header("  ,  ").is_empty());
+    }
+}
@@ -1,3 +1,6 @@
+pub mod cache;
+pub mod client;
 pub mod git_providers;
 mod github;
 mod gitlab;
+pub mod link_header;
@@ -4,15 +4,18 @@ use clap::Parser;
 use grgry::{
     cli::{
         alias, clone,
-        commands::{Commands, ProfileCommands},
-        mass,
+        commands::{Commands, ConfigCommands, ProfileCommands},
+        list_repos, lock, mass, message_template,
         profile::{
-            activate_profile_prompt, add_profile_prompt, delete_profile_prompt, show_profile,
+            activate_profile, add_profile, delete_profile, edit_profile, export_profile,
+            import_profiles, rename_profile, show_profile, validate_profiles,
         },
-        quick, update,
+        quick, restore, set_discovery_ignore, show_config, sync_forks, update, CloneOptions,
+        ListReposOptions, QuickOptions, SyncForksOptions,
     },
-    config::config::Config,
-    utils::cmd::run_cmd_s,
+    config::config::{Config, Profile},
+    git_api::client::build_client,
+    utils::{cmd::run_cmd_s, message::MessageTemplate},
 };
 use reqwest::Client;
 
@@ -23,12 +26,29 @@ use reqwest::Client;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        help = "Use this profile instead of the activated one for this command."
+    )]
+    profile: Option<String>,
 }
 
 #[tokio::main]
 async fn main() {
     let mut config: Config = Config::new();
     let cli: Cli = Cli::parse();
+    if let Some(profile) = &cli.profile {
+        config.use_profile(profile);
+    }
+    match config.settings.color.as_deref() {
+        Some("always") => colored::control::set_override(true),
+        Some("never") => colored::control::set_override(false),
+        _ => {}
+    }
+    set_discovery_ignore(config.settings.ignore.clone());
     let client = Arc::new(
         Client::builder()
             .http2_prior_knowledge()
@@ -41,40 +61,159 @@ async fn main() {
         Commands::Clone {
             directory,
             force,
-            user,
+            list_args,
+            repo,
+            from_file,
+            all_my_orgs,
             branch,
             regex_args,
             dry_run,
+            jobs,
+            quiet,
+            with_upstream,
         } => {
             let (regex, reverse) = regex_args.get_regex_args(".*"); //TODO: default as global variable
+            let client: Arc<Client> = profile_client(&config);
             clone(
+                directory.as_deref(),
+                CloneOptions {
+                    force: *force,
+                    list: list_args.list_options(),
+                    repo: *repo,
+                    from_file: from_file.clone(),
+                    all_my_orgs: *all_my_orgs,
+                    branch: branch.to_string(),
+                    regex,
+                    reverse,
+                    dry_run: *dry_run,
+                    jobs: jobs.or(config.settings.jobs),
+                    quiet: *quiet,
+                    with_upstream: *with_upstream,
+                },
+                config,
+                client,
+            )
+            .await;
+        }
+        Commands::List {
+            directory,
+            list_args,
+            all_my_orgs,
+            regex_args,
+            format,
+        } => {
+            let (regex, reverse) = regex_args.get_regex_args(".*");
+            let client: Arc<Client> = profile_client(&config);
+            list_repos(
+                directory.as_deref(),
+                ListReposOptions {
+                    list: list_args.list_options(),
+                    all_my_orgs: *all_my_orgs,
+                    regex,
+                    reverse,
+                    format: format
+                        .clone()
+                        .or(config.settings.format.clone())
+                        .unwrap_or("table".to_string()),
+                },
+                config,
+                client,
+            )
+            .await;
+        }
+        Commands::SyncForks {
+            directory,
+            list_args,
+            regex_args,
+            api,
+            dry_run,
+        } => {
+            let (regex, reverse) = regex_args.get_regex_args(".*");
+            let client: Arc<Client> = profile_client(&config);
+            sync_forks(
                 directory,
-                *force,
-                *user,
-                branch.to_string(),
-                &regex,
-                reverse,
-                *dry_run,
+                SyncForksOptions {
+                    list: list_args.list_options(),
+                    regex,
+                    reverse,
+                    api: *api,
+                    dry_run: *dry_run,
+                },
                 config,
                 client,
             )
             .await;
         }
+        Commands::Lock {
+            output,
+            strict,
+            regex_args,
+        } => {
+            let (regex, reverse) = regex_args.get_regex_args(".*");
+            lock(output, &regex, reverse, *strict);
+        }
+        Commands::Restore {
+            lockfile,
+            target,
+            jobs,
+            dry_run,
+        } => restore(
+            lockfile,
+            target.as_deref(),
+            jobs.or(config.settings.jobs),
+            *dry_run,
+            &config,
+        ),
         Commands::Quick {
             message,
             force,
+            force_with_lease,
+            amend,
+            branch,
+            no_pull,
+            no_push,
+            push_to,
+            commit_type,
+            scope,
+            body_file,
+            trailer,
+            message_pattern,
+            pathspec,
+            tracked_only,
             regex_args,
             skip_interactive,
             dry_run,
         } => {
             let (regex, reverse) = regex_args.get_regex_args(".*");
+            let template: Option<MessageTemplate> = message.as_deref().map(|message: &str| {
+                message_template(
+                    message,
+                    commit_type.as_deref(),
+                    scope.as_deref(),
+                    body_file.as_deref(),
+                    trailer,
+                    message_pattern
+                        .as_deref()
+                        .or(config.settings.commitpattern.as_deref()),
+                )
+            });
             quick(
-                message,
-                *force,
-                &regex,
-                reverse,
-                *skip_interactive,
-                *dry_run,
+                template.as_ref(),
+                QuickOptions {
+                    force: *force,
+                    force_with_lease: *force_with_lease,
+                    regex,
+                    reverse,
+                    skip_interactive: *skip_interactive,
+                    dry_run: *dry_run,
+                    pathspecs: pathspec.clone(),
+                    tracked_only: *tracked_only,
+                    no_pull: *no_pull,
+                    no_push: *no_push,
+                    amend: *amend,
+                    branch: branch.clone(),
+                    push_to: push_to.clone(),
+                },
                 config,
             );
         }
@@ -86,14 +225,42 @@ async fn main() {
         } => {
             println!("Stuck3");
             let (regex, reverse) = regex_args.get_regex_args(".*");
-            mass(command, &regex, reverse, *skip_interactive, *dry_run)
+            mass(
+                command,
+                &regex,
+                reverse,
+                *skip_interactive,
+                *dry_run,
+                &config,
+            )
         }
-        Commands::Profile { sub } => match &sub {
-            ProfileCommands::Activate => activate_profile_prompt(&mut config),
-            ProfileCommands::Add => add_profile_prompt(&mut config),
-            ProfileCommands::Delete => delete_profile_prompt(&mut config),
+        Commands::Profile { sub } => match sub.as_ref() {
+            ProfileCommands::Activate { name } => activate_profile(name.as_deref(), &mut config),
+            ProfileCommands::Add {
+                name,
+                fields,
+                activate,
+            } => add_profile(name.as_deref(), fields, *activate, &mut config),
+            ProfileCommands::Edit { name, fields } => edit_profile(name, fields, &mut config),
+            ProfileCommands::Rename { name, new_name } => {
+                rename_profile(name, new_name, &mut config)
+            }
+            ProfileCommands::Validate { name } => validate_profiles(name.as_deref(), &config),
+            ProfileCommands::Delete { name } => delete_profile(name.as_deref(), &mut config),
+            ProfileCommands::Export {
+                name,
+                redact_secrets,
+            } => export_profile(name, *redact_secrets, &config),
+            ProfileCommands::Import {
+                file,
+                force,
+                allow_token_sources,
+            } => import_profiles(file, *force, *allow_token_sources, &mut config),
             ProfileCommands::Show { all } => show_profile(*all, config),
         },
+        Commands::Config { sub } => match &sub {
+            ConfigCommands::Show { origin } => show_config(*origin, &config),
+        },
         Commands::Alias { command } => {
             let mass_command = alias(command.to_vec());
             let cli = Cli::parse_from(mass_command);
@@ -105,10 +272,17 @@ async fn main() {
             } = cli.command
             {
                 let (regex, reverse) = regex_args.get_regex_args(".*");
-                mass(&command, &regex, reverse, skip_interactive, dry_run)
+                mass(
+                    &command,
+                    &regex,
+                    reverse,
+                    skip_interactive,
+                    dry_run,
+                    &config,
+                )
             }
         }
-        Commands::Update {} => match update(client).await {
+        Commands::Update => match update(client).await {
             Ok(_) => {
                 println!("Successfully updated grgry, check new version with grgry --version.")
             }
@@ -118,3 +292,9 @@ async fn main() {
            // }
     }
 }
+
+// The API client uses the network settings of the active profile
+fn profile_client(config: &Config) -> Arc<Client> {
+    let profile: &Profile = config.active_profile();
+    Arc::new(build_client(profile))
+}
@@ -11,7 +11,7 @@ pub fn run_cmd_o(command: &mut Command, test: bool) -> String {
     if test {
         let cmd_str: String = command_to_string(command);
         println!("Executing: {}", cmd_str);
-        return String::from("");
+        String::from("")
     } else {
         let output: Output = command.output().expect("Failed to execute command!");
         if !output.status.success() {
@@ -23,7 +23,7 @@ pub fn run_cmd_o(command: &mut Command, test: bool) -> String {
             std::process::exit(1);
         }
 
-        return String::from_utf8_lossy(&output.stdout).trim().to_string();
+        String::from_utf8_lossy(&output.stdout).trim().to_string()
     }
 }
 
@@ -31,7 +31,7 @@ pub fn run_cmd_o_soft(command: &mut Command, test: bool) -> (String, bool) {
     if test {
         let cmd_str: String = command_to_string(command);
         println!("Executing: {}", cmd_str);
-        return (String::from(""), true);
+        (String::from(""), true)
     } else {
         let output: Output = command.output().expect("Failed to execute command!");
         if !output.status.success() {
@@ -40,10 +40,28 @@ pub fn run_cmd_o_soft(command: &mut Command, test: bool) -> (String, bool) {
                 false,
             );
         }
-        return (
+        (
             String::from_utf8_lossy(&output.stdout).trim().to_string(),
             true,
-        );
+        )
+    }
+}
+
+// Like run_cmd_o but returns the trimmed stderr as error instead of exiting the process
+pub fn run_cmd_r(command: &mut Command, test: bool) -> Result<String, String> {
+    if test {
+        let cmd_str: String = command_to_string(command);
+        println!("Executing: {}", cmd_str);
+        return Ok(String::from(""));
+    }
+    let output: Output = match command.output() {
+        Ok(output) => output,
+        Err(err) => return Err(format!("Failed to execute command: {}", err)),
+    };
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
     }
 }
 
@@ -51,7 +69,7 @@ pub fn run_cmd_s(mut command: &mut Command, test: bool, silent: bool) -> bool {
     if test {
         let cmd_str: String = command_to_string(command);
         println!("Executing: {}", cmd_str);
-        return true;
+        true
     } else {
         command = if silent {
             command.stdout(Stdio::null()).stderr(Stdio::null())
@@ -67,12 +85,35 @@ pub fn run_cmd_s(mut command: &mut Command, test: bool, silent: bool) -> bool {
             );
             std::process::exit(1);
         }
-        return status.success();
+        status.success()
     }
 }
 
-pub fn create_git_cmd(repo_path: &str) -> Command {
+// Passed to every git command as -c key=value, e.g. the network settings of the profile
+#[derive(Debug, Clone, Default)]
+pub struct GitConfig(Vec<String>);
+
+impl GitConfig {
+    pub fn new(entries: Vec<String>) -> Self {
+        GitConfig(entries)
+    }
+
+    // For commands which only work on the local repo and never reach a remote
+    pub fn local() -> Self {
+        GitConfig::default()
+    }
+}
+
+pub fn git_cmd(config: &GitConfig) -> Command {
     let mut command = Command::new("git");
+    for entry in &config.0 {
+        command.arg("-c").arg(entry);
+    }
+    command
+}
+
+pub fn create_git_cmd(config: &GitConfig, repo_path: &str) -> Command {
+    let mut command = git_cmd(config);
     command.arg("-C").arg(repo_path);
-    return command;
+    command
 }
@@ -1,5 +1,8 @@
 use std::{
-    sync::{mpsc, Arc},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
     thread::{self, available_parallelism},
 };
 
@@ -31,8 +34,11 @@ where
     T: Send + Sync + 'static,
     R: Send + 'static,
 {
-    let num_threads = available_parallelism().unwrap().into();
-    run_in_threads(num_threads, items, task)
+    run_in_threads(default_threads(), items, task)
+}
+
+pub fn default_threads() -> usize {
+    available_parallelism().map(|n| n.get()).unwrap_or(1)
 }
 
 pub fn run_in_threads<F, T, R>(num_threads: usize, items: Vec<T>, task: F) -> Vec<R>
@@ -41,8 +47,11 @@ where
     T: Send + Sync + 'static,
     R: Send + 'static,
 {
+    let num_threads: usize = num_threads.max(1);
     let task: Arc<F> = Arc::new(task);
     let items: Arc<Vec<T>> = Arc::new(items);
+    // Every thread takes the next free item so one slow item does not hold back others
+    let next_item: Arc<AtomicUsize> = Arc::new(AtomicUsize::new(0));
     let (tx_result, rx_result) = mpsc::channel();
     //If threads are not set by default the available threads are taken from std::thread
     let mut handles: Vec<thread::JoinHandle<()>> = vec![];
@@ -50,13 +59,15 @@ where
     for thread_id in 0..num_threads {
         let task: Arc<F> = Arc::clone(&task);
         let items: Arc<Vec<T>> = Arc::clone(&items);
+        let next_item: Arc<AtomicUsize> = Arc::clone(&next_item);
         let tx_result: mpsc::Sender<R> = tx_result.clone();
-        let handle: thread::JoinHandle<()> = thread::spawn(move || {
-            for i in (thread_id..items.len()).step_by(num_threads) {
-                let item: &T = &items[i];
-                let result: R = task(thread_id, item);
-                tx_result.send(result).expect("Failed to send result");
-            }
+        let handle: thread::JoinHandle<()> = thread::spawn(move || loop {
+            let i: usize = next_item.fetch_add(1, Ordering::SeqCst);
+            let Some(item) = items.get(i) else {
+                break;
+            };
+            let result: R = task(thread_id, item);
+            tx_result.send(result).expect("Failed to send result");
         });
         handles.push(handle);
     }
@@ -0,0 +1,285 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use reqwest::Url;
+
+use crate::git_api::git_providers::Repo;
+
+pub const DEFAULT_LAYOUT: &str = "{full_path}";
+const PLACEHOLDERS: [&str; 5] = ["host", "full_path", "namespace", "namespace_last", "name"];
+
+// A layout template decides where a repository lives locally relative to the targetbasepath.
+// Supported placeholders are {host}, {full_path}, {namespace}, {namespace_last} and {name}.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    template: String,
+}
+
+impl Layout {
+    pub fn new(template: &str) -> Result<Self, String> {
+        let template: &str = if template.trim().is_empty() {
+            DEFAULT_LAYOUT
+        } else {
+            template.trim()
+        };
+        let mut rest: &str = template;
+        while let Some(start) = rest.find('{') {
+            let end: usize = match rest[start..].find('}') {
+                Some(end) => start + end,
+                None => return Err(format!("Unclosed placeholder in layout {}", template)),
+            };
+            let placeholder: &str = &rest[start + 1..end];
+            if !PLACEHOLDERS.contains(&placeholder) {
+                return Err(format!(
+                    "Unknown placeholder {{{}}} in layout {}, supported are {}",
+                    placeholder,
+                    template,
+                    PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", ")
+                ));
+            }
+            rest = &rest[end + 1..];
+        }
+        // The repos must stay below the targetbasepath, a project config can set the layout too
+        if template.starts_with(['/', '\\']) || Path::new(template).is_absolute() {
+            return Err(format!(
+                "Layout {} is absolute, it has to be relative to the targetbasepath",
+                template
+            ));
+        }
+        if template.split(['/', '\\']).any(is_relative_segment) {
+            return Err(format!(
+                "Layout {} contains . or .. segments, which are not allowed",
+                template
+            ));
+        }
+        Ok(Layout {
+            template: template.to_string(),
+        })
+    }
+
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+
+    // Renders the relative path of a repository, e.g. "group/sub/repo" for {full_path}
+    pub fn render(&self, repo: &dyn Repo) -> Result<String, String> {
+        let full_path: &str = repo.full_path().trim_matches('/');
+        let (namespace, name): (&str, &str) = full_path.rsplit_once('/').unwrap_or(("", full_path));
+        let namespace_last: &str = namespace.rsplit('/').next().unwrap_or("");
+        let rendered: String = self
+            .template
+            .replace("{host}", &repo_host(repo))
+            .replace("{full_path}", full_path)
+            .replace("{namespace_last}", namespace_last)
+            .replace("{namespace}", namespace)
+            .replace("{name}", name);
+        // The path of a repo comes from the provider and could contain .. as well
+        let segments: Vec<&str> = rendered
+            .split(['/', '\\'])
+            .filter(|segment: &&str| !segment.is_empty())
+            .collect();
+        if segments.is_empty()
+            || segments
+                .iter()
+                .any(|segment: &&str| is_relative_segment(segment))
+        {
+            return Err(format!(
+                "Layout {} maps {} to the invalid path {}",
+                self.template,
+                repo.full_path(),
+                rendered
+            ));
+        }
+        Ok(segments.join("/"))
+    }
+
+    pub fn destination(&self, target_basepath: &str, repo: &dyn Repo) -> Result<PathBuf, String> {
+        self.render(repo)
+            .map(|path: String| Path::new(target_basepath).join(path))
+    }
+
+    // Returns every local path which more than one repository would be cloned into
+    pub fn find_collisions(
+        &self,
+        repos: &[Box<dyn Repo>],
+    ) -> Result<BTreeMap<String, Vec<String>>, String> {
+        let mut paths: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for repo in repos {
+            paths
+                .entry(self.render(repo.as_ref())?)
+                .or_default()
+                .push(repo.full_path().to_string());
+        }
+        paths.retain(|_, full_paths: &mut Vec<String>| full_paths.len() > 1);
+        Ok(paths)
+    }
+}
+
+fn is_relative_segment(segment: &str) -> bool {
+    segment == "." || segment == ".."
+}
+
+pub fn repo_host(repo: &dyn Repo) -> String {
+    if let Some(host) = Url::parse(repo.http_url())
+        .ok()
+        .and_then(|url: Url| url.host_str().map(str::to_string))
+    {
+        return host;
+    }
+    // ssh urls look like git@host:group/repo.git
+    repo.ssh_url()
+        .split_once('@')
+        .and_then(|(_, rest)| rest.split([':', '/']).next())
+        .unwrap_or("")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestRepo {
+        ssh_url: String,
+        http_url: String,
+        full_path: String,
+    }
+
+    impl Repo for TestRepo {
+        fn ssh_url(&self) -> &str {
+            &self.ssh_url
+        }
+
+        fn http_url(&self) -> &str {
+            &self.http_url
+        }
+
+        fn full_path(&self) -> &str {
+            &self.full_path
+        }
+    }
+
+    fn repo(full_path: &str) -> TestRepo {
+        TestRepo {
+            ssh_url: format!("git@gitlab.example.com:{}.git", full_path),
+            http_url: format!("https://gitlab.example.com/{}.git", full_path),
+            full_path: full_path.to_string(),
+        }
+    }
+
+    fn render(template: &str, full_path: &str) -> String {
+        Layout::new(template)
+            .unwrap()
+            .render(&repo(full_path))
+            .unwrap()
+    }
+
+    #[test]
+    fn renders_every_placeholder() {
+        let full_path: &str = "group/sub/repo";
+        assert_eq!(render("", full_path), "group/sub/repo");
+        assert_eq!(
+            render("{host}/{full_path}", full_path),
+            "gitlab.example.com/group/sub/repo"
+        );
+        assert_eq!(render("{namespace}/{name}", full_path), "group/sub/repo");
+        assert_eq!(render("{namespace_last}/{name}", full_path), "sub/repo");
+        assert_eq!(render("{name}", full_path), "repo");
+    }
+
+    #[test]
+    fn drops_empty_segments_of_top_level_repos() {
+        assert_eq!(render("{namespace}/{name}", "repo"), "repo");
+        assert_eq!(render("{namespace_last}/{name}", "/repo/"), "repo");
+    }
+
+    #[test]
+    fn rejects_unknown_and_unclosed_placeholders() {
+        assert!(Layout::new("{owner}/{name}")
+            .unwrap_err()
+            .contains("Unknown placeholder {owner}"));
+        assert!(Layout::new("{name")
+            .unwrap_err()
+            .contains("Unclosed placeholder"));
+    }
+
+    #[test]
+    fn rejects_templates_leaving_the_targetbasepath() {
+        for template in [
+            "../../{name}",
+            "{namespace}/../{name}",
+            "./{name}",
+            "{host}\\..\\{name}",
+            "/{full_path}",
+            "\\{full_path}",
+        ] {
+            assert!(Layout::new(template).is_err(), "{} was accepted", template);
+        }
+        assert!(Layout::new("  {host}/{full_path}  ").is_ok());
+    }
+
+    #[test]
+    fn rejects_repo_paths_leaving_the_targetbasepath() {
+        let layout: Layout = Layout::new("{namespace_last}/{name}").unwrap();
+        for full_path in ["group/..", "../repo", "group/./repo", "//"] {
+            assert!(
+                layout.render(&repo(full_path)).is_err(),
+                "{} was accepted",
+                full_path
+            );
+        }
+        let repos: Vec<Box<dyn Repo>> = vec![Box::new(repo("group/.."))];
+        assert!(layout.find_collisions(&repos).is_err());
+        assert!(layout.destination("/base", &repo("group/..")).is_err());
+        assert_eq!(
+            layout.destination("/base", &repo("group/repo")).unwrap(),
+            PathBuf::from("/base/group/repo")
+        );
+    }
+
+    #[test]
+    fn finds_repos_mapped_to_the_same_path() {
+        let repos: Vec<Box<dyn Repo>> = vec![
+            Box::new(repo("team-a/api")),
+            Box::new(repo("team-b/api")),
+            Box::new(repo("team-b/web")),
+        ];
+        let collisions: BTreeMap<String, Vec<String>> = Layout::new("{name}")
+            .unwrap()
+            .find_collisions(&repos)
+            .unwrap();
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions["api"], vec!["team-a/api", "team-b/api"]);
+        assert!(Layout::new("{full_path}")
+            .unwrap()
+            .find_collisions(&repos)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn takes_the_host_from_the_http_url() {
+        let repo: TestRepo = TestRepo {
+            ssh_url: "git@ssh.example.com:group/repo.git".to_string(),
+            http_url: "https://example.com:8443/group/repo.git".to_string(),
+            full_path: "group/repo".to_string(),
+        };
+        assert_eq!(repo_host(&repo), "example.com");
+    }
+
+    #[test]
+    fn falls_back_to_the_host_of_the_ssh_url() {
+        for ssh_url in [
+            "git@ssh.example.com:group/repo.git",
+            "ssh://git@ssh.example.com/group/repo.git",
+        ] {
+            let repo: TestRepo = TestRepo {
+                ssh_url: ssh_url.to_string(),
+                http_url: String::new(),
+                full_path: "group/repo".to_string(),
+            };
+            assert_eq!(repo_host(&repo), "ssh.example.com");
+        }
+    }
+}
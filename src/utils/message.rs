@@ -0,0 +1,237 @@
+use regex::Regex;
+
+const PLACEHOLDERS: [&str; 4] = ["repo", "path", "branch", "changed"];
+
+// The values a message is rendered with for one repository
+#[derive(Clone)]
+pub struct MessageValues {
+    pub repo: String,
+    pub path: String,
+    pub branch: String,
+    pub changed: usize,
+}
+
+// A commit message template of grgry quick. The subject is built as type(scope): message like
+// Conventional Commits, followed by the body and the trailers.
+// Supported placeholders are {repo}, {path}, {branch} and {changed}, other braces are kept as they are.
+#[derive(Debug, Clone)]
+pub struct MessageTemplate {
+    template: String,
+    pattern: Option<Regex>,
+}
+
+impl MessageTemplate {
+    pub fn new(
+        message: &str,
+        commit_type: Option<&str>,
+        scope: Option<&str>,
+        body: Option<&str>,
+        trailers: &[String],
+        pattern: Option<&str>,
+    ) -> Result<Self, String> {
+        let subject: String = match (commit_type, scope) {
+            (Some(commit_type), Some(scope)) => format!("{}({}): {}", commit_type, scope, message),
+            (Some(commit_type), None) => format!("{}: {}", commit_type, message),
+            (None, Some(_)) => return Err("--scope can only be used with --type".to_string()),
+            (None, None) => message.to_string(),
+        };
+        for trailer in trailers {
+            let valid: bool = trailer
+                .split_once(':')
+                .map(|(key, value)| {
+                    !key.is_empty()
+                        && !key.contains(char::is_whitespace)
+                        && !value.trim().is_empty()
+                })
+                .unwrap_or(false);
+            if !valid {
+                return Err(format!(
+                    "Trailer {} must look like Key: value, e.g. Refs: JIRA-123",
+                    trailer
+                ));
+            }
+        }
+        let mut parts: Vec<String> = vec![subject];
+        if let Some(body) = body.map(str::trim).filter(|body: &&str| !body.is_empty()) {
+            parts.push(body.to_string());
+        }
+        if !trailers.is_empty() {
+            parts.push(trailers.join("\n"));
+        }
+        let template: String = parts.join("\n\n");
+        let pattern: Option<Regex> = match pattern {
+            Some(pattern) => Some(
+                Regex::new(pattern)
+                    .map_err(|err| format!("Invalid message pattern {}: {}", pattern, err))?,
+            ),
+            None => None,
+        };
+        Ok(MessageTemplate { template, pattern })
+    }
+
+    // Renders the message of one repository and checks its subject against the pattern
+    pub fn render(&self, values: &MessageValues) -> Result<String, String> {
+        let message: String = replace_placeholders(&self.template, values);
+        let subject: &str = message.lines().next().unwrap_or("");
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(subject) {
+                return Err(format!(
+                    "Commit message {:?} does not match {}",
+                    subject,
+                    pattern.as_str()
+                ));
+            }
+        }
+        Ok(message)
+    }
+}
+
+// Replaces the placeholders in one pass, so a value containing a placeholder is not replaced again
+fn replace_placeholders(template: &str, values: &MessageValues) -> String {
+    let mut message: String = String::with_capacity(template.len());
+    let mut rest: &str = template;
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let placeholder: Option<&str> = rest[1..]
+            .find('}')
+            .map(|end: usize| &rest[1..end + 1])
+            .filter(|name: &&str| PLACEHOLDERS.contains(name));
+        match placeholder {
+            Some(name) => {
+                match name {
+                    "repo" => message.push_str(&values.repo),
+                    "path" => message.push_str(&values.path),
+                    "branch" => message.push_str(&values.branch),
+                    _ => message.push_str(&values.changed.to_string()),
+                }
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                message.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    message.push_str(rest);
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> MessageValues {
+        MessageValues {
+            repo: "api".to_string(),
+            path: "group/api".to_string(),
+            branch: "main".to_string(),
+            changed: 3,
+        }
+    }
+
+    fn render(template: &MessageTemplate) -> String {
+        template.render(&values()).unwrap()
+    }
+
+    #[test]
+    fn replaces_placeholders() {
+        let template: MessageTemplate = MessageTemplate::new(
+            "{repo} at {path} on {branch}: {changed} files",
+            None,
+            None,
+            None,
+            &[],
+            None,
+        )
+        .unwrap();
+        assert_eq!(render(&template), "api at group/api on main: 3 files");
+    }
+
+    #[test]
+    fn keeps_other_braces() {
+        let body: &str =
+            "fn main() { println!(\"{}\", 1); }\n{\"key\": {\"nested\": true}}\n{unknown} {";
+        let template: MessageTemplate =
+            MessageTemplate::new("fix {repo}", None, None, Some(body), &[], None).unwrap();
+        assert_eq!(render(&template), format!("fix api\n\n{}", body));
+    }
+
+    #[test]
+    fn does_not_replace_placeholders_in_values() {
+        let values: MessageValues = MessageValues {
+            repo: "{branch}".to_string(),
+            ..values()
+        };
+        let template: MessageTemplate =
+            MessageTemplate::new("{repo} {branch}", None, None, None, &[], None).unwrap();
+        assert_eq!(template.render(&values).unwrap(), "{branch} main");
+    }
+
+    #[test]
+    fn builds_conventional_subject_with_body_and_trailers() {
+        let trailers: Vec<String> = vec![
+            "Refs: JIRA-123".to_string(),
+            "Co-authored-by: Me <me@x>".to_string(),
+        ];
+        let template: MessageTemplate = MessageTemplate::new(
+            "bump dependencies",
+            Some("chore"),
+            Some("{repo}"),
+            Some("  The body.\n"),
+            &trailers,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            render(&template),
+            "chore(api): bump dependencies\n\nThe body.\n\nRefs: JIRA-123\nCo-authored-by: Me <me@x>"
+        );
+    }
+
+    #[test]
+    fn builds_subject_without_scope() {
+        let template: MessageTemplate =
+            MessageTemplate::new("add login", Some("feat"), None, None, &[], None).unwrap();
+        assert_eq!(render(&template), "feat: add login");
+    }
+
+    #[test]
+    fn rejects_scope_without_type() {
+        assert!(MessageTemplate::new("msg", None, Some("api"), None, &[], None).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_trailers() {
+        for trailer in ["no colon", "Key:", "Two words: value", ": value"] {
+            let trailers: Vec<String> = vec![trailer.to_string()];
+            assert!(
+                MessageTemplate::new("msg", None, None, None, &trailers, None).is_err(),
+                "{} should be rejected",
+                trailer
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_pattern() {
+        assert!(MessageTemplate::new("msg", None, None, None, &[], Some("(")).is_err());
+    }
+
+    #[test]
+    fn checks_only_the_rendered_subject_against_the_pattern() {
+        let template: MessageTemplate = MessageTemplate::new(
+            "{repo}",
+            Some("feat"),
+            None,
+            Some("not checked"),
+            &[],
+            Some("^feat: api$"),
+        )
+        .unwrap();
+        assert_eq!(render(&template), "feat: api\n\nnot checked");
+        let template: MessageTemplate =
+            MessageTemplate::new("{repo}", None, None, None, &[], Some("^feat: ")).unwrap();
+        assert!(template.render(&values()).is_err());
+    }
+}
@@ -1,2 +1,5 @@
 pub mod cmd;
 pub mod helper;
+pub mod layout;
+pub mod message;
+pub mod progress;
@@ -0,0 +1,200 @@
+use std::{
+    io::{stdout, IsTerminal, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use colored::Colorize;
+
+use super::helper::MessageType;
+
+const REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+const MAX_LABEL_LENGTH: usize = 60;
+// Used if the height of the terminal can't be determined
+const DEFAULT_ROWS: usize = 24;
+
+struct ProgressState {
+    done: usize,
+    failed: usize,
+    workers: Vec<Option<(String, Instant)>>,
+    drawn_lines: usize,
+}
+
+// Shows the overall count and what every worker is currently busy with.
+// If stdout is no terminal the messages are simply printed line by line,
+// in quiet mode only errors are printed.
+pub struct Progress {
+    title: String,
+    total: usize,
+    quiet: bool,
+    live: bool,
+    started: Instant,
+    state: Mutex<ProgressState>,
+    running: AtomicBool,
+}
+
+impl Progress {
+    pub fn new(title: &str, total: usize, workers: usize, quiet: bool, live: bool) -> Arc<Self> {
+        let progress: Arc<Progress> = Arc::new(Progress {
+            title: title.to_string(),
+            total,
+            quiet,
+            live: live && !quiet && stdout().is_terminal(),
+            started: Instant::now(),
+            state: Mutex::new(ProgressState {
+                done: 0,
+                failed: 0,
+                workers: vec![None; workers],
+                drawn_lines: 0,
+            }),
+            running: AtomicBool::new(true),
+        });
+        if progress.live {
+            // Redraw regularly so the elapsed time of long running repos keeps ticking
+            let ticker: Arc<Progress> = Arc::clone(&progress);
+            thread::spawn(move || {
+                while ticker.running.load(Ordering::Relaxed) {
+                    ticker.redraw();
+                    thread::sleep(REFRESH_INTERVAL);
+                }
+            });
+        }
+        progress
+    }
+
+    pub fn start(&self, worker: usize, label: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(slot) = state.workers.get_mut(worker) {
+            *slot = Some((truncate_label(label), Instant::now()));
+        }
+        self.draw(&mut state);
+    }
+
+    pub fn finish(&self, worker: usize, result: Result<String, String>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(slot) = state.workers.get_mut(worker) {
+            *slot = None;
+        }
+        state.done += 1;
+        match result {
+            Ok(message) => self.print(&mut state, &message, MessageType::Success),
+            Err(message) => {
+                state.failed += 1;
+                self.print(&mut state, &message, MessageType::Error)
+            }
+        }
+    }
+
+    pub fn println(&self, message: &str, message_type: MessageType) {
+        let mut state = self.state.lock().unwrap();
+        self.print(&mut state, message, message_type);
+    }
+
+    // Stops the live view and returns the amount of finished and failed items
+    pub fn stop(&self) -> (usize, usize) {
+        self.running.store(false, Ordering::Relaxed);
+        let mut state = self.state.lock().unwrap();
+        self.clear(&mut state);
+        (state.done, state.failed)
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    fn redraw(&self) {
+        let mut state = self.state.lock().unwrap();
+        self.draw(&mut state);
+    }
+
+    fn print(&self, state: &mut ProgressState, message: &str, message_type: MessageType) {
+        if self.quiet && !matches!(message_type, MessageType::Error) {
+            return;
+        }
+        self.clear(state);
+        match message_type {
+            MessageType::Error => eprintln!("{}", message.red()),
+            MessageType::Success => println!("{}", message.green()),
+            MessageType::Neutral => println!("{}", message),
+        }
+        self.draw(state);
+    }
+
+    fn clear(&self, state: &mut ProgressState) {
+        if self.live && state.drawn_lines > 0 {
+            // Move the cursor to the first line of the view and clear everything below
+            print!("\x1b[{}A\x1b[J", state.drawn_lines);
+            let _ = stdout().flush();
+            state.drawn_lines = 0;
+        }
+    }
+
+    fn draw(&self, state: &mut ProgressState) {
+        if !self.live || !self.running.load(Ordering::Relaxed) {
+            return;
+        }
+        self.clear(state);
+        let mut lines: Vec<String> = vec![format!(
+            "{} [{}/{}] {} failed, {:.0?} elapsed",
+            self.title,
+            state.done,
+            self.total,
+            state.failed,
+            self.elapsed()
+        )
+        .bold()
+        .to_string()];
+        // Lines which scroll out of the terminal can't be cleared anymore, so with more workers
+        // than rows the idle ones are left out first and the rest is summarized in one line
+        let max_workers: usize = terminal_rows().saturating_sub(2).max(1);
+        let hide_idle: bool = state.workers.len() > max_workers;
+        let mut workers: Vec<String> = state
+            .workers
+            .iter()
+            .enumerate()
+            .filter_map(|(worker, current)| match current {
+                Some((label, started)) => Some(format!(
+                    "  #{:<3} {} ({:.1}s)",
+                    worker + 1,
+                    label,
+                    started.elapsed().as_secs_f32()
+                )),
+                None if hide_idle => None,
+                None => Some(format!("  #{:<3} {}", worker + 1, "idle".dimmed())),
+            })
+            .collect();
+        if workers.len() > max_workers {
+            let hidden: usize = workers.len() - (max_workers - 1);
+            workers.truncate(max_workers - 1);
+            workers.push(
+                format!("  ... {} more busy workers", hidden)
+                    .dimmed()
+                    .to_string(),
+            );
+        }
+        lines.extend(workers);
+        println!("{}", lines.join("\n"));
+        let _ = stdout().flush();
+        state.drawn_lines = lines.len();
+    }
+}
+
+fn terminal_rows() -> usize {
+    crossterm::terminal::size()
+        .map(|(_, rows): (u16, u16)| rows as usize)
+        .unwrap_or(DEFAULT_ROWS)
+}
+
+// Long labels would wrap and break the line count of the live view, so keep the end of it
+fn truncate_label(label: &str) -> String {
+    let length: usize = label.chars().count();
+    if length <= MAX_LABEL_LENGTH {
+        return label.to_string();
+    }
+    let tail: String = label.chars().skip(length - MAX_LABEL_LENGTH + 3).collect();
+    format!("...{}", tail)
+}